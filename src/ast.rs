@@ -0,0 +1,199 @@
+use std::ops::Range;
+
+pub use crate::attributes::Attributes;
+
+/// A parsed Kami document.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Document {
+	pub blocks: Vec<Block>,
+	/// The footnotes that are referenced in the document, in the order of their numbers.
+	pub footnotes: Vec<Footnote>,
+}
+
+/// A footnote definition, written as `[^label]: text` anywhere in the document.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Footnote {
+	pub label: String,
+	/// Footnotes are numbered from 1, in the order they're first referenced.
+	pub number: usize,
+	/// How many times the footnote is referenced.
+	pub references: usize,
+	pub attributes: Attributes,
+	pub content: Vec<Inline>,
+	/// Blocks written on the indented lines after the definition.
+	pub children: Vec<Block>,
+	pub span: Range<usize>,
+	pub line: usize,
+}
+
+/// Every node has the byte range it comes from in the source, and the line where it starts.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Block {
+	pub kind: BlockKind,
+	pub attributes: Attributes,
+	pub span: Range<usize>,
+	/// 1-based.
+	pub line: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum BlockKind {
+	Paragraph(Vec<Inline>),
+	Header { level: usize, content: Vec<Inline> },
+	Image(Image),
+	/// A line starting with an HTML tag. `tag` is empty for lines starting with `<>`.
+	Html { tag: String, content: Vec<Inline> },
+	/// A fenced code block, with its lines exactly as they were written.
+	Code { language: Option<String>, content: String },
+	/// A blockquote, which can contain any other blocks. `attribution` is who or what is being quoted, from a last line starting with `-- `.
+	Quote { content: Vec<Block>, attribution: Option<Vec<Inline>> },
+	/// A table, with its rows split into a `<thead>`, `<tbody>` and `<tfoot>`. Tables written without any head or foot only have a body.
+	Table { caption: Option<Vec<Inline>>, columns: Vec<TableColumn>, head: Vec<TableRow>, body: Vec<TableRow>, foot: Vec<TableRow> },
+	/// An image, table or code block with a caption, written on a line starting with `!!` right after it. Figures are numbered from 1 in the order they appear in the document.
+	Figure { number: usize, content: Box<Block>, caption: Vec<Inline> },
+	DefinitionList { entries: Vec<DefinitionEntry> },
+	/// A `[[toc]]` line, which is replaced by the table of contents of the document.
+	Toc { entries: Vec<TocEntry> },
+	List { ordered: bool, items: Vec<ListItem> },
+}
+
+/// An image, written as `!photo.png!`, with the other versions of it and its size: `!photo.png, photo-2x.png 2x =640x480!`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Image {
+	pub source: String,
+	/// Other files the browser can choose from, each with its descriptor like `2x` or `800w`. The descriptor is empty for files that don't have one.
+	pub srcset: Vec<(String, String)>,
+	pub width: Option<u32>,
+	pub height: Option<u32>,
+}
+
+/// A header in a table of contents, with the headers under it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TocEntry {
+	pub level: usize,
+	pub id: Option<String>,
+	/// The text of the header, without any formatting.
+	pub title: String,
+	pub children: Vec<TocEntry>,
+}
+
+/// One or more terms followed by the definitions they share. The items of definitions can have blocks inside them, like list items.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DefinitionEntry {
+	pub terms: Vec<ListItem>,
+	pub definitions: Vec<ListItem>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ListItem {
+	pub attributes: Attributes,
+	pub content: Vec<Inline>,
+	/// Blocks written on the indented lines after the item, followed by lists nested under it.
+	pub children: Vec<Block>,
+	pub span: Range<usize>,
+	pub line: usize,
+}
+
+/// A column of a table, from its column specification row like `|:---|:-:{.price}|---:|`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TableColumn {
+	pub align: Option<Alignment>,
+	/// The attributes of the column's `<col>` element.
+	pub attributes: Attributes,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Alignment {
+	Left,
+	Center,
+	Right,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TableRow {
+	pub attributes: Attributes,
+	pub cells: Vec<TableCell>,
+	pub span: Range<usize>,
+	pub line: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TableCell {
+	pub header: bool,
+	/// The alignment of the column the cell starts in.
+	pub align: Option<Alignment>,
+	pub attributes: Attributes,
+	pub content: Vec<Inline>,
+	/// Blocks of a cell written as `:::`, with its content on the lines after its row.
+	pub children: Vec<Block>,
+	pub span: Range<usize>,
+	pub line: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Inline {
+	pub kind: InlineKind,
+	pub attributes: Attributes,
+	pub span: Range<usize>,
+	pub line: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum InlineKind {
+	Text(String),
+	/// Text escaped with `\=...=`, or anything else that is output verbatim.
+	Raw(String),
+	Html(String),
+	Code(String),
+	LineBreak,
+	Image(Image),
+	/// `destination` is `None` for a bare `[name]`.
+	Link { destination: Option<String>, title: Option<String>, content: Vec<Inline> },
+	/// A `[name][label]` reference link whose label isn't defined anywhere. Defined ones are turned into links.
+	LinkRef { label: String, content: Vec<Inline> },
+	Bold(Vec<Inline>),
+	Strong(Vec<Inline>),
+	Italic(Vec<Inline>),
+	Emphasis(Vec<Inline>),
+	Sub(Vec<Inline>),
+	Sup(Vec<Inline>),
+	Span(Vec<Inline>),
+	Strike(Vec<Inline>),
+	Under(Vec<Inline>),
+	/// A `[^label]` reference to a footnote. `number` is `None` if the footnote isn't defined anywhere. `reference` counts the references to the same footnote from 1, in order.
+	FootnoteRef { label: String, number: Option<usize>, reference: usize },
+}
+
+impl InlineKind {
+	/// The inlines inside this one, for the kinds that have any.
+	pub fn children(&self) -> Option<&Vec<Inline>> {
+		match self {
+			InlineKind::Link { content, .. } | InlineKind::LinkRef { content, .. } | InlineKind::Bold(content) | InlineKind::Strong(content) | InlineKind::Italic(content) | InlineKind::Emphasis(content) | InlineKind::Sub(content) | InlineKind::Sup(content) | InlineKind::Span(content) | InlineKind::Strike(content) | InlineKind::Under(content) => Some(content),
+			_ => None,
+		}
+	}
+
+	pub fn children_mut(&mut self) -> Option<&mut Vec<Inline>> {
+		match self {
+			InlineKind::Link { content, .. } | InlineKind::LinkRef { content, .. } | InlineKind::Bold(content) | InlineKind::Strong(content) | InlineKind::Italic(content) | InlineKind::Emphasis(content) | InlineKind::Sub(content) | InlineKind::Sup(content) | InlineKind::Span(content) | InlineKind::Strike(content) | InlineKind::Under(content) => Some(content),
+			_ => None,
+		}
+	}
+}
+
+/// The text of some inlines without any formatting. Raw HTML, images and footnote references are left out.
+pub fn plain_text(inlines: &[Inline]) -> String {
+	let mut out = String::new();
+	for inline in inlines.iter() {
+		match &inline.kind {
+			InlineKind::Text(text) | InlineKind::Code(text) => out += text,
+			InlineKind::LineBreak => out.push(' '),
+			kind => {
+				if let Some(children) = kind.children() {
+					out += &plain_text(children);
+				}
+			},
+		}
+	}
+	out
+}
@@ -0,0 +1,176 @@
+use crate::diagnostic::Diagnostic;
+use std::fmt;
+use std::iter::Peekable;
+use std::str::CharIndices;
+
+/// The `{#id .class key="value"}` sequence attached to an element.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Attributes {
+	pub id: Option<String>,
+	pub classes: Vec<String>,
+	/// Every other attribute, in the order they were written. Bare attributes like `hidden` have no value.
+	pub pairs: Vec<(String, Option<String>)>,
+}
+
+impl Attributes {
+	/// Parses an attribute sequence. The surrounding braces are optional. `offset` is the position of `input` in the source, and is only used for the diagnostics.
+	pub fn parse(input: &str, offset: usize) -> (Self, Vec<Diagnostic>) {
+		let mut out = Self::default();
+		let mut warnings: Vec<Diagnostic> = Vec::new();
+		let mut body = input;
+		let mut body_offset = offset;
+		if let Some(x) = body.strip_prefix('{') {
+			body = x;
+			body_offset += 1;
+			match body.strip_suffix('}') {
+				Some(x) => body = x,
+				None => warnings.push(Diagnostic::warning("unclosed-attributes", "Attribute sequence isn't closed with a }".to_owned(), offset..offset + input.len())),
+			}
+		}
+		let mut chars = body.char_indices().peekable();
+		loop {
+			while chars.next_if(|(_, x)| x.is_whitespace()).is_some() {}
+			let Some(&(start, cha)) = chars.peek() else { break };
+			match cha {
+				'#' | '.' => {
+					chars.next();
+					let name = take_word(&mut chars, |x| !x.is_whitespace());
+					let span = body_offset + start..body_offset + start + 1 + name.len();
+					if name.is_empty() {
+						warnings.push(Diagnostic::warning("empty-attribute", format!("Found a {} without a name after it", cha), span));
+					} else if cha == '#' {
+						if let Some(id) = &out.id {
+							warnings.push(Diagnostic::warning("duplicate-id", format!("The id {} replaces the id {} given before it", name, id), span));
+						}
+						out.id = Some(name);
+					} else {
+						out.add_class(&name);
+					}
+				},
+				_ => {
+					let key = take_word(&mut chars, |x| !x.is_whitespace() && x != '=');
+					let value = chars.next_if(|(_, x)| *x == '=').map(|_| take_value(&mut chars, body, body_offset, &mut warnings));
+					let end = chars.peek().map(|(x, _)| *x).unwrap_or(body.len());
+					let span = body_offset + start..body_offset + end;
+					if key.is_empty() {
+						warnings.push(Diagnostic::warning("empty-attribute", "Found a value without an attribute name before it".to_owned(), span));
+					} else if !is_valid_name(&key) {
+						warnings.push(Diagnostic::warning("invalid-attribute", format!("{} isn't a valid attribute name, so it was ignored", key), span));
+					} else {
+						if out.has(&key) {
+							warnings.push(Diagnostic::warning("duplicate-attribute", format!("The attribute {} is given more than once, only the last one is used", key), span));
+						}
+						out.set(&key, value.as_deref());
+					}
+				},
+			}
+		}
+		(out, warnings)
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.id.is_none() && self.classes.is_empty() && self.pairs.is_empty()
+	}
+
+	/// Returns the value of an attribute. Bare attributes have no value, use `has` for those.
+	pub fn get(&self, key: &str) -> Option<&str> {
+		self.pairs.iter().find(|(k, _)| k == key).and_then(|(_, v)| v.as_deref())
+	}
+
+	pub fn has(&self, key: &str) -> bool {
+		self.pairs.iter().any(|(k, _)| k == key)
+	}
+
+	/// Sets the value of an attribute, keeping its position if it was already there.
+	pub fn set(&mut self, key: &str, value: Option<&str>) {
+		let value = value.map(|x| x.to_owned());
+		match self.pairs.iter_mut().find(|(k, _)| k == key) {
+			Some(pair) => pair.1 = value,
+			None => self.pairs.push((key.to_owned(), value)),
+		}
+	}
+
+	pub fn remove(&mut self, key: &str) {
+		self.pairs.retain(|(k, _)| k != key);
+	}
+
+	pub fn add_class(&mut self, class: &str) {
+		if !self.classes.iter().any(|x| x == class) {
+			self.classes.push(class.to_owned());
+		}
+	}
+
+	/// Adds the attributes of `other` to these. Where both have the same attribute or an id, the one in `other` wins.
+	pub fn merge(&mut self, other: &Attributes) {
+		if other.id.is_some() {
+			self.id = other.id.clone();
+		}
+		for class in other.classes.iter() {
+			self.add_class(class);
+		}
+		for (key, value) in other.pairs.iter() {
+			self.set(key, value.as_deref());
+		}
+	}
+}
+
+/// Writes the attributes back as a Kami attribute sequence, like `{#id .class key="value"}`.
+impl fmt::Display for Attributes {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let mut parts: Vec<String> = Vec::new();
+		if let Some(id) = &self.id {
+			parts.push("#".to_owned() + id);
+		}
+		for class in self.classes.iter() {
+			parts.push(".".to_owned() + class);
+		}
+		for (key, value) in self.pairs.iter() {
+			match value {
+				Some(value) => parts.push(key.to_owned() + "=\"" + &value.replace('\\', "\\\\").replace('"', "\\\"") + "\""),
+				None => parts.push(key.to_owned()),
+			}
+		}
+		write!(f, "{{{}}}", parts.join(" "))
+	}
+}
+
+fn take_word(chars: &mut Peekable<CharIndices>, accept: impl Fn(char) -> bool) -> String {
+	let mut out = String::new();
+	while let Some((_, cha)) = chars.next_if(|(_, x)| accept(*x)) {
+		out.push(cha);
+	}
+	out
+}
+
+/// Reads a value after an `=`. It can be surrounded by double or single quotes, in which case backslashes escape the next character.
+fn take_value(chars: &mut Peekable<CharIndices>, body: &str, offset: usize, warnings: &mut Vec<Diagnostic>) -> String {
+	let Some((start, quote)) = chars.next_if(|(_, x)| *x == '"' || *x == '\'') else {
+		return take_word(chars, |x| !x.is_whitespace())
+	};
+	let mut out = String::new();
+	loop {
+		match chars.next() {
+			None => {
+				warnings.push(Diagnostic::warning("unclosed-quote", format!("Attribute value isn't closed with a {}", quote), offset + start..offset + body.len()));
+				break;
+			},
+			Some((_, '\\')) => {
+				if let Some((_, cha)) = chars.next() {
+					out.push(cha);
+				}
+			},
+			Some((_, cha)) if cha == quote => break,
+			Some((_, cha)) => out.push(cha),
+		}
+	}
+	if let Some(&(pos, cha)) = chars.peek() {
+		if !cha.is_whitespace() {
+			warnings.push(Diagnostic::warning("missing-space", "Attributes should be separated by spaces".to_owned(), offset + pos..offset + pos + cha.len_utf8()));
+		}
+	}
+	out
+}
+
+fn is_valid_name(name: &str) -> bool {
+	!name.chars().any(|x| x.is_control() || matches!(x, '"' | '\'' | '<' | '>' | '/' | '{' | '}' | '\\'))
+}
@@ -0,0 +1,121 @@
+use crate::ast::{Attributes, Inline, InlineKind};
+use std::ops::Range;
+
+/// Turns the bare URLs and email addresses in the text of `inlines` into links. Text that is already inside a link is left alone.
+pub fn autolink(inlines: &mut Vec<Inline>) {
+	let mut out: Vec<Inline> = Vec::new();
+	for mut inline in inlines.drain(..) {
+		if !matches!(inline.kind, InlineKind::Link { .. } | InlineKind::LinkRef { .. }) {
+			if let Some(children) = inline.kind.children_mut() {
+				autolink(children);
+			}
+		}
+		// Escaped characters split text into several pieces, so they're joined back before looking for links
+		match (out.last_mut(), &inline.kind) {
+			(Some(Inline { kind: InlineKind::Text(last), attributes, span, .. }), InlineKind::Text(text)) if attributes.is_empty() && inline.attributes.is_empty() => {
+				*last += text;
+				span.end = inline.span.end;
+			},
+			_ => out.push(inline),
+		}
+	}
+	for inline in out.into_iter() {
+		match &inline.kind {
+			InlineKind::Text(text) if inline.attributes.is_empty() => split_links(text, &inline, inlines),
+			_ => inlines.push(inline),
+		}
+	}
+}
+
+/// Splits a piece of text into text and links, and adds them to `out`.
+fn split_links(text: &str, inline: &Inline, out: &mut Vec<Inline>) {
+	// The span of the text only matches its content when nothing in it was escaped
+	let exact = inline.span.len() == text.len();
+	let span = |range: Range<usize>| if exact { inline.span.start + range.start..inline.span.start + range.end } else { inline.span.clone() };
+	let push = |out: &mut Vec<Inline>, kind: InlineKind, range: Range<usize>| out.push(Inline { kind, attributes: Attributes::default(), span: span(range), line: inline.line });
+	let mut last = 0;
+	for (start, word) in words(text) {
+		let (offset, word) = trim_word(word);
+		let Some(destination) = link_destination(word) else { continue };
+		let start = start + offset;
+		if start > last {
+			push(out, InlineKind::Text(text[last..start].to_owned()), last..start);
+		}
+		let end = start + word.len();
+		let content = vec![Inline { kind: InlineKind::Text(word.to_owned()), attributes: Attributes::default(), span: span(start..end), line: inline.line }];
+		push(out, InlineKind::Link { destination: Some(destination), title: None, content }, start..end);
+		last = end;
+	}
+	if last < text.len() {
+		push(out, InlineKind::Text(text[last..].to_owned()), last..text.len());
+	}
+}
+
+/// The words of `text` with their positions.
+fn words(text: &str) -> Vec<(usize, &str)> {
+	let mut out = Vec::new();
+	let mut start = None;
+	for (pos, cha) in text.char_indices() {
+		match (cha.is_whitespace(), start) {
+			(true, Some(x)) => {
+				out.push((x, &text[x..pos]));
+				start = None;
+			},
+			(false, None) => start = Some(pos),
+			_ => (),
+		}
+	}
+	if let Some(x) = start {
+		out.push((x, &text[x..]));
+	}
+	out
+}
+
+/// The length of the URL or email address at the start of `text`, without the punctuation after it.
+pub(crate) fn link_len(text: &str) -> Option<usize> {
+	let word = text.split(char::is_whitespace).next()?;
+	let (offset, word) = trim_word(word);
+	(offset == 0 && !word.contains('\\') && link_destination(word).is_some()).then_some(word.len())
+}
+
+/// Removes the punctuation around a word that is most likely not part of a URL, like the period that ends a sentence or the parentheses around it. Returns how much was removed from the start.
+fn trim_word(word: &str) -> (usize, &str) {
+	let trimmed = word.trim_start_matches(['(', '<', '"', '\'']);
+	let offset = word.len() - trimmed.len();
+	let mut trimmed = trimmed;
+	loop {
+		let shorter = trimmed.trim_end_matches(['.', ',', ':', ';', '!', '?', '"', '\'', '>']);
+		// A closing parenthesis is kept only if the URL opened one, like in Wikipedia links
+		let shorter = match shorter.strip_suffix(')') {
+			Some(x) if shorter.matches(')').count() > shorter.matches('(').count() => x,
+			_ => shorter,
+		};
+		if shorter.len() == trimmed.len() {
+			break;
+		}
+		trimmed = shorter;
+	}
+	(offset, trimmed)
+}
+
+/// Where a word should link to, if it's a URL or an email address.
+fn link_destination(word: &str) -> Option<String> {
+	for scheme in ["http://", "https://"] {
+		if word.len() > scheme.len() && word.get(..scheme.len()).is_some_and(|x| x.eq_ignore_ascii_case(scheme)) {
+			return Some(word.to_owned())
+		}
+	}
+	if word.get(..4).is_some_and(|x| x.eq_ignore_ascii_case("www.")) && is_domain(word[4..].split(['/', '?', '#']).next().unwrap_or("")) {
+		return Some("http://".to_owned() + word)
+	}
+	let (local, domain) = word.split_once('@')?;
+	let valid_local = !local.is_empty() && local.chars().all(|x| x.is_alphanumeric() || matches!(x, '.' | '_' | '%' | '+' | '-'));
+	if valid_local && domain.contains('.') && is_domain(domain) {
+		return Some("mailto:".to_owned() + word)
+	}
+	None
+}
+
+fn is_domain(domain: &str) -> bool {
+	!domain.is_empty() && domain.split('.').all(|x| !x.is_empty() && x.chars().all(|x| x.is_alphanumeric() || x == '-'))
+}
@@ -0,0 +1,143 @@
+use kami_parser::format;
+
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+const USAGE: &str = "Usage: kamifmt [OPTIONS] [INPUT]...
+
+Formats Kami documents in place. INPUT can be a file, a directory (every .km file in it is formatted) or - to format stdin to stdout. With no INPUT, stdin is formatted.
+
+Documents that would render differently after formatting are reported and left as they are.
+
+Options:
+      --check    Don't write anything, list the documents that aren't formatted and exit with 1 if there are any
+  -h, --help     Print this message
+  -V, --version  Print the version";
+
+struct Args {
+	inputs: Vec<String>,
+	check: bool,
+}
+
+fn parse_args(args: impl Iterator<Item = String>) -> Result<Option<Args>, String> {
+	let mut out = Args { inputs: Vec::new(), check: false };
+	let mut only_inputs = false;
+	for arg in args {
+		if only_inputs || arg == "-" || !arg.starts_with('-') {
+			out.inputs.push(arg);
+			continue;
+		}
+		match arg.as_str() {
+			"--check" => out.check = true,
+			"--" => only_inputs = true,
+			"-h" | "--help" => {
+				println!("{}", USAGE);
+				return Ok(None)
+			},
+			"-V" | "--version" => {
+				println!("kamifmt {}", env!("CARGO_PKG_VERSION"));
+				return Ok(None)
+			},
+			_ => return Err(format!("Unknown option {}", arg)),
+		}
+	}
+	if out.inputs.is_empty() {
+		out.inputs.push("-".to_owned());
+	}
+	Ok(Some(out))
+}
+
+fn read_input(input: &str) -> Result<String, String> {
+	if input == "-" {
+		let mut out = String::new();
+		io::stdin().read_to_string(&mut out).map_err(|x| format!("Couldn't read stdin: {}", x))?;
+		Ok(out)
+	} else {
+		fs::read_to_string(input).map_err(|x| format!("Couldn't read {}: {}", input, x))
+	}
+}
+
+/// Finds every .km file under `dir`, sorted so output is deterministic.
+fn find_documents(dir: &Path, out: &mut Vec<PathBuf>) -> Result<(), String> {
+	let entries = fs::read_dir(dir).map_err(|x| format!("Couldn't read {}: {}", dir.display(), x))?;
+	let mut paths: Vec<PathBuf> = entries.filter_map(|x| x.ok()).map(|x| x.path()).collect();
+	paths.sort();
+	for path in paths {
+		if path.is_dir() {
+			find_documents(&path, out)?;
+		} else if path.extension().is_some_and(|x| x == "km") {
+			out.push(path);
+		}
+	}
+	Ok(())
+}
+
+/// Formats a single document, writing it back unless checking. Returns whether it wasn't formatted already, or `None` if it couldn't be formatted.
+fn format_document(input: &str, args: &Args) -> Result<Option<bool>, String> {
+	let name = if input == "-" { "<stdin>" } else { input };
+	let source = read_input(input)?;
+	let out = match format::format(&source) {
+		Ok(out) => out,
+		Err(x) => {
+			eprintln!("{}: {}", name, x);
+			if input == "-" && !args.check {
+				io::stdout().write_all(source.as_bytes()).map_err(|x| format!("Couldn't write to stdout: {}", x))?;
+			}
+			return Ok(None)
+		},
+	};
+	let changed = out != source;
+	if args.check {
+		if changed {
+			println!("{}", name);
+		}
+	} else if input == "-" {
+		io::stdout().write_all(out.as_bytes()).map_err(|x| format!("Couldn't write to stdout: {}", x))?;
+	} else if changed {
+		fs::write(input, &out).map_err(|x| format!("Couldn't write {}: {}", input, x))?;
+	}
+	Ok(Some(changed))
+}
+
+/// Formats everything, returning whether any document couldn't be formatted, or wasn't formatted when checking.
+fn run(args: &Args) -> Result<bool, String> {
+	let mut failed = false;
+	for input in args.inputs.iter() {
+		let root = Path::new(input);
+		let documents = if root.is_dir() {
+			let mut documents = Vec::new();
+			find_documents(root, &mut documents)?;
+			documents.into_iter().map(|x| x.to_string_lossy().into_owned()).collect()
+		} else {
+			vec![input.to_owned()]
+		};
+		for document in documents {
+			match format_document(&document, args)? {
+				None => failed = true,
+				Some(changed) => failed |= changed && args.check,
+			}
+		}
+	}
+	Ok(failed)
+}
+
+fn main() -> ExitCode {
+	let args = match parse_args(std::env::args().skip(1)) {
+		Ok(Some(args)) => args,
+		Ok(None) => return ExitCode::SUCCESS,
+		Err(x) => {
+			eprintln!("kamifmt: {}\n\n{}", x, USAGE);
+			return ExitCode::from(2)
+		},
+	};
+	match run(&args) {
+		Ok(true) => ExitCode::from(1),
+		Ok(false) => ExitCode::SUCCESS,
+		Err(x) => {
+			eprintln!("kamifmt: {}", x);
+			ExitCode::from(2)
+		},
+	}
+}
@@ -0,0 +1,62 @@
+use std::fmt;
+use std::ops::Range;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+	Warning,
+	Error,
+}
+
+/// A problem found while parsing, pointing at the part of the source that caused it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+	pub severity: Severity,
+	/// A short, stable identifier for the kind of problem, like `unclosed-token`.
+	pub code: &'static str,
+	pub message: String,
+	/// Byte range in the source.
+	pub span: Range<usize>,
+	/// 1-based line of the start of `span`.
+	pub line: usize,
+	/// 1-based column (in characters) of the start of `span`.
+	pub column: usize,
+}
+
+impl Diagnostic {
+	pub(crate) fn warning(code: &'static str, message: String, span: Range<usize>) -> Self {
+		Self { severity: Severity::Warning, code, message, span, line: 0, column: 0 }
+	}
+
+	pub(crate) fn error(code: &'static str, message: String, span: Range<usize>) -> Self {
+		Self { severity: Severity::Error, code, message, span, line: 0, column: 0 }
+	}
+
+	/// Fills in `line` and `column` from `span`, given the whole source.
+	pub fn locate(&mut self, input: &str) {
+		let start = floor_char_boundary(input, self.span.start);
+		let before = &input[..start];
+		self.line = before.matches('\n').count() + 1;
+		let line_start = before.rfind('\n').map(|x| x + 1).unwrap_or(0);
+		self.column = input[line_start..start].chars().count() + 1;
+	}
+}
+
+impl fmt::Display for Diagnostic {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let severity = match self.severity {
+			Severity::Warning => "warning",
+			Severity::Error => "error",
+		};
+		write!(f, "{}[{}]: {} (line {}, column {})", severity, self.code, self.message, self.line, self.column)
+	}
+}
+
+fn floor_char_boundary(input: &str, mut index: usize) -> usize {
+	if index >= input.len() {
+		return input.len()
+	}
+	while !input.is_char_boundary(index) {
+		index -= 1;
+	}
+	index
+}
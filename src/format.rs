@@ -0,0 +1,619 @@
+use crate::ast::{plain_text, Alignment, Attributes, Block, BlockKind, Footnote, Image, Inline, InlineKind, ListItem, TableColumn, TableRow};
+use crate::diagnostic::Diagnostic;
+use crate::syntax::{self, LinkDefinition};
+use crate::tables::layout;
+use std::ops::Range;
+
+/// Tables are aligned with tabs, which are taken to go to the next multiple of this many columns.
+pub const TAB_WIDTH: usize = 4;
+
+/// Formats a Kami document: every block is written again from the parse tree, with attributes in the order `{#id .class key="value"}`, list markers like `**` and `##.`, tables aligned with tabs and the same escaping everywhere. Blank lines between blocks are kept, but never more than one.
+///
+/// The formatted document always renders to the same HTML as the original. Blocks that would render differently once formatted are kept as they were written, and if the whole document still doesn't render the same, an error is returned instead.
+pub fn format(input: &str) -> Result<String, Diagnostic> {
+	let source = syntax::parse_source(input);
+	let mut entries: Vec<Entry> = source.blocks.iter().map(Entry::Block).collect();
+	entries.extend(source.footnotes.iter().map(Entry::Footnote));
+	entries.extend(source.links.iter().map(Entry::Link));
+	entries.sort_by_key(|x| x.span().start);
+	let formatter = Formatter { input };
+	let mut out = String::new();
+	// Where the last entry ends in the source, and whether it was kept as it was written
+	let mut end = 0;
+	let mut kept = false;
+	let mut previous: Option<&Block> = None;
+	for entry in entries.iter() {
+		let span = entry.span();
+		let nested = span.start < end;
+		if nested && kept {
+			// A definition written inside the block before it, which is already there
+			continue;
+		}
+		let formatted = formatter.entry(entry);
+		let original = if nested { &formatted[..] } else { skip_blank_lines(&input[end..line_end(input, span.end)]) };
+		kept = !renders_same(entry, &formatted, original);
+		let text = if kept { original } else { &formatted[..] };
+		if !out.is_empty() {
+			out.push('\n');
+			let block = match entry {
+				Entry::Block(block) => Some(*block),
+				_ => None,
+			};
+			let needed = matches!((previous, block), (Some(x), Some(y)) if needs_blank(x, y));
+			if needed || (!nested && formatter.blank_between(end, span.start)) {
+				out.push('\n');
+			}
+			if matches!((previous, block), (Some(x), Some(y)) if !kept && same_list(x, y)) {
+				out += "{}\n";
+			}
+		}
+		out += text;
+		previous = match entry {
+			Entry::Block(block) => Some(*block),
+			_ => previous,
+		};
+		end = end.max(span.end);
+	}
+	// A \r at the very end would be read as part of the line break
+	if !out.is_empty() && !out.ends_with('\r') {
+		out.push('\n');
+	}
+	if syntax::parse(input).0 != syntax::parse(&out).0 {
+		let mut error = Diagnostic::error("format-changes-output", "Formatting the document would change its HTML, so it was left as it is".to_owned(), 0..input.len());
+		error.locate(input);
+		return Err(error)
+	}
+	Ok(out)
+}
+
+/// Something written on lines of its own at the top of a document. Footnote and link definitions are taken out of wherever they were written, so they're put back in the order of their position.
+enum Entry<'a> {
+	Block(&'a Block),
+	Footnote(&'a Footnote),
+	Link(&'a LinkDefinition),
+}
+
+impl Entry<'_> {
+	fn span(&self) -> Range<usize> {
+		match self {
+			Entry::Block(block) => block.span.clone(),
+			Entry::Footnote(note) => note.span.clone(),
+			Entry::Link(link) => link.span.clone(),
+		}
+	}
+}
+
+/// Whether an entry renders the same once formatted. Definitions don't render anything on their own, so they're given a reference to render them through.
+fn renders_same(entry: &Entry, formatted: &str, original: &str) -> bool {
+	let reference = match entry {
+		Entry::Block(_) => String::new(),
+		Entry::Footnote(note) => format!("[^{}]\n", note.label),
+		Entry::Link(link) => format!("[link][{}]\n", link.label),
+	};
+	syntax::parse(&(reference.clone() + formatted)).0 == syntax::parse(&(reference + original)).0
+}
+
+struct Formatter<'a> {
+	/// The source of the document, to see where it had blank lines.
+	input: &'a str,
+}
+
+impl Formatter<'_> {
+	fn entry(&self, entry: &Entry) -> String {
+		match entry {
+			Entry::Block(block) => self.block(block, ""),
+			Entry::Footnote(note) => {
+				let item = ListItem { attributes: note.attributes.clone(), content: note.content.clone(), children: note.children.clone(), span: note.span.clone(), line: note.line };
+				self.item(&format!("[^{}]: ", note.label), &item, true, "")
+			},
+			Entry::Link(link) => {
+				let mut out = format!("[{}]: {}", link.label, destination(&link.destination));
+				if let Some(title) = &link.title {
+					out += &(" ".to_owned() + &quote_title(title));
+				}
+				if !link.attributes.is_empty() {
+					out += &(" ".to_owned() + &link.attributes.to_string());
+				}
+				out
+			},
+		}
+	}
+
+	/// Whether the source has a blank line between two positions. Lines with only the `>` of a blockquote count as blank.
+	fn blank_between(&self, end: usize, start: usize) -> bool {
+		if end >= start {
+			return false
+		}
+		let gap: Vec<&str> = self.input[end..start].split('\n').collect();
+		gap.len() > 2 && gap[1..gap.len() - 1].iter().any(|x| x.trim_matches(|x: char| x == '>' || x.is_whitespace()).is_empty())
+	}
+
+	/// Writes a sequence of blocks, starting every line with `prefix`.
+	fn blocks(&self, blocks: &[Block], prefix: &str) -> String {
+		let mut out = String::new();
+		for (iter, block) in blocks.iter().enumerate() {
+			if let Some(previous) = iter.checked_sub(1).map(|x| &blocks[x]) {
+				out.push('\n');
+				if needs_blank(previous, block) || self.blank_between(previous.span.end, block.span.start) {
+					out += &(prefix.trim_end().to_owned() + "\n");
+				}
+				if same_list(previous, block) {
+					out += &(prefix.to_owned() + "{}\n");
+				}
+			}
+			out += &self.block(block, prefix);
+		}
+		out
+	}
+
+	fn block(&self, block: &Block, prefix: &str) -> String {
+		let attributes = &block.attributes;
+		// The attributes of most blocks go on the line before them
+		let before = if attributes.is_empty() { String::new() } else { prefix.to_owned() + &attributes.to_string() + "\n" };
+		match &block.kind {
+			BlockKind::Paragraph(content) => {
+				let breaks = Breaks::Paragraph(prefix);
+				let starts_text = match content.first().map(|x| &x.kind) {
+					Some(InlineKind::Html(_)) => false,
+					Some(InlineKind::Image(_)) => matches!(content.get(1).map(|x| &x.kind), None | Some(InlineKind::LineBreak)),
+					Some(InlineKind::Text(text)) => !text.starts_with(char::is_whitespace),
+					_ => true,
+				};
+				if !attributes.is_empty() || !starts_text {
+					prefix.to_owned() + &attributes.to_string() + &inlines(content, breaks, Position::Middle, false)
+				} else {
+					prefix.to_owned() + &inlines(content, breaks, Position::Line, false)
+				}
+			},
+			BlockKind::Header { level, content } => {
+				let marker = "#".repeat(*level);
+				let content = inlines(content, Breaks::Paragraph(prefix), Position::Middle, false);
+				if attributes.is_empty() {
+					format!("{}{} {}", prefix, marker, content)
+				} else {
+					format!("{}{}{}{}", prefix, marker, attributes, content)
+				}
+			},
+			BlockKind::Image(image) => format!("{}!{}!{}", prefix, image_source(image), attributes_after(attributes)),
+			BlockKind::Html { tag, content } => {
+				let tag = if tag.is_empty() { "<>".to_owned() } else { escape_html(tag) };
+				prefix.to_owned() + &tag + &attributes_after(attributes) + &inlines(content, Breaks::Paragraph(prefix), Position::Middle, false)
+			},
+			BlockKind::Code { language, content } => {
+				// The fence has to be longer than any line of backticks in the code, or that line would close it
+				let longest = content.split('\n').filter(|x| !x.trim_end().is_empty() && x.trim_end().chars().all(|x| x == '`')).map(|x| x.trim_end().len()).max().unwrap_or(0);
+				let fence = "`".repeat(longest.max(2) + 1);
+				let mut out = format!("{}{}{}{}\n", prefix, fence, language.as_deref().unwrap_or_default(), attributes_after(attributes));
+				for line in content.split_terminator('\n') {
+					out += &(if line.is_empty() { prefix.trim_end().to_owned() } else { prefix.to_owned() + line });
+					out.push('\n');
+				}
+				out + prefix + &fence
+			},
+			BlockKind::Quote { content, attribution } => {
+				let inner = prefix.to_owned() + "> ";
+				let mut out = before + &self.blocks(content, &inner);
+				if let Some(attribution) = attribution {
+					if !content.is_empty() {
+						out.push('\n');
+					}
+					out += &(inner + "-- " + &inlines(attribution, Breaks::Escaped, Position::Run, false));
+				} else if content.is_empty() {
+					out += &(prefix.to_owned() + ">");
+				}
+				out
+			},
+			BlockKind::Table { caption, columns, head, body, foot } => before + &self.table(caption.as_deref(), columns, [head, body, foot], prefix),
+			BlockKind::Figure { content, caption, .. } => {
+				let caption = inlines(caption, Breaks::Escaped, Position::Run, false);
+				format!("{}\n{}!!{} {}", self.block(content, prefix), prefix, attributes_after(attributes), caption).trim_end().to_owned()
+			},
+			BlockKind::DefinitionList { entries } => {
+				let mut lines = Vec::new();
+				for entry in entries.iter() {
+					lines.extend(entry.terms.iter().map(|x| self.item("; ", x, true, prefix)));
+					lines.extend(entry.definitions.iter().map(|x| self.item(": ", x, true, prefix)));
+				}
+				before + &lines.join("\n")
+			},
+			BlockKind::Toc { .. } => before + prefix + "[[toc]]",
+			BlockKind::List { .. } => before + &self.list(block, prefix, 0),
+		}
+	}
+
+	/// Writes a list with markers for its depth. Lists nested in the last items are written with deeper markers under them.
+	fn list(&self, list: &Block, prefix: &str, depth: usize) -> String {
+		let BlockKind::List { ordered, items } = &list.kind else { return String::new() };
+		let marker = if *ordered { "#".repeat(depth + 1) + ". " } else { "*".repeat(depth + 1) + " " };
+		let mut lines = Vec::new();
+		for item in items.iter() {
+			// Nested lists can be written with deeper markers if they're the last thing in the item, and only if they don't have attributes, since an attribute sequence would end the list
+			let mut split = item.children.len();
+			while let Some(Block { kind: BlockKind::List { ordered, .. }, attributes, .. }) = split.checked_sub(1).map(|x| &item.children[x]) {
+				let after = item.children.get(split).map(|x| matches!(x.kind, BlockKind::List { ordered: o, .. } if o == *ordered));
+				if !attributes.is_empty() || after == Some(true) {
+					break;
+				}
+				split -= 1;
+			}
+			let (body, nested) = item.children.split_at(split);
+			let body = ListItem { children: body.to_vec(), ..item.clone() };
+			lines.push(self.item(&marker, &body, false, prefix));
+			lines.extend(nested.iter().map(|x| self.list(x, prefix, depth + 1)));
+		}
+		lines.join("\n")
+	}
+
+	/// Writes a list item, a term or definition, or a footnote after its `marker`, with its blocks indented under it after a blank line. `leading` is for the ones whose attributes go after the marker with a space, which is taken out when they're read.
+	fn item(&self, marker: &str, item: &ListItem, leading: bool, prefix: &str) -> String {
+		let inner = prefix.to_owned() + "  ";
+		let mut out = prefix.to_owned() + marker;
+		if !item.attributes.is_empty() {
+			out += &item.attributes.to_string();
+			if leading {
+				out.push(' ');
+			}
+		}
+		out += &inlines(&item.content, Breaks::Item(&inner), if leading { Position::Run } else { Position::Middle }, false);
+		if !item.children.is_empty() {
+			out += &("\n".to_owned() + prefix.trim_end() + "\n" + &self.blocks(&item.children, &inner));
+		}
+		out
+	}
+
+	/// Writes a table with its columns lined up with tabs. A cell that spans several columns reaches the end of the last one, and the cells after columns taken by a rowspan from above line up with their columns.
+	fn table(&self, caption: Option<&[Inline]>, columns: &[TableColumn], sections: [&Vec<TableRow>; 3], prefix: &str) -> String {
+		let start = prefix.chars().count();
+		let mut rows: Vec<Row> = Vec::new();
+		for (section, rows_of) in sections.iter().enumerate() {
+			let layouts = layout(rows_of, &mut Vec::new());
+			for (row, layout) in rows_of.iter().zip(layouts) {
+				let mut cells = Vec::new();
+				let mut children = Vec::new();
+				let mut last = 0;
+				for (cell, column) in row.cells.iter().zip(layout.starts) {
+					let span = |key: &str, max: usize| cell.attributes.get(key).and_then(|x| x.parse::<usize>().ok()).unwrap_or(1).clamp(1, max);
+					let end = column + span("colspan", 1000);
+					// Every cell of the head is a header when there's a column specification under it
+					let header = cell.header && (section != 0 || columns.is_empty());
+					let content = if cell.children.is_empty() {
+						double_backslashes(&inlines(&cell.content, Breaks::Escaped, Position::Run, false))
+					} else {
+						children.push(&cell.children[..]);
+						":::".to_owned()
+					};
+					cells.push((last, end, format!("|{} {}", cell_starter(&cell.attributes, header), content)));
+					last = end;
+				}
+				rows.push(Row { cells, attributes: attributes_after(&row.attributes), children });
+			}
+		}
+		let specs: Vec<(usize, usize, String)> = columns.iter().enumerate().map(|(iter, column)| {
+			let spec = match column.align {
+				Some(Alignment::Left) => ":--",
+				Some(Alignment::Center) => ":-:",
+				Some(Alignment::Right) => "--:",
+				None => "---",
+			};
+			(iter, iter + 1, format!("|{}{}", spec, attributes_after(&column.attributes)))
+		}).collect();
+		// Where each column starts, as the column of the line it's at
+		let width = rows.iter().flat_map(|x| x.cells.iter()).chain(specs.iter()).map(|x| x.1).max().unwrap_or(0);
+		let mut stops = vec![start];
+		for column in 1..=width {
+			let mut stop = stops[column - 1];
+			for (before, _, text) in rows.iter().flat_map(|x| x.cells.iter()).chain(specs.iter()).filter(|x| x.1 == column) {
+				// At least one tab after every cell, so the next one doesn't run into it
+				stop = stop.max(stops[*before] + text.chars().count() + 1);
+			}
+			stops.push(stop.div_ceil(TAB_WIDTH) * TAB_WIDTH);
+		}
+		let pad = |text: &str, before: usize, end: usize| {
+			let mut out = text.to_owned();
+			let mut at = stops[before] + text.chars().count();
+			while at < stops[end] {
+				at = (at / TAB_WIDTH + 1) * TAB_WIDTH;
+				out.push('\t');
+			}
+			out
+		};
+		let mut lines = Vec::new();
+		if let Some(caption) = caption {
+			lines.push(prefix.to_owned() + "|+ " + &double_backslashes(&inlines(caption, Breaks::Escaped, Position::Run, false)));
+		}
+		let mut rows = rows.into_iter();
+		let [head, body, foot] = sections;
+		let mut write_rows = |lines: &mut Vec<String>, count: usize| {
+			for Row { cells, attributes, children } in rows.by_ref().take(count) {
+				let mut line = prefix.to_owned();
+				for (before, end, text) in cells.iter() {
+					line += &pad(text, *before, *end);
+				}
+				// A row without cells still needs something after its |, or it isn't a row
+				let attributes = if cells.is_empty() && attributes.is_empty() { "{}".to_owned() } else { attributes };
+				lines.push(line + "|" + &attributes);
+				for blocks in children {
+					let content = self.blocks(blocks, prefix);
+					let longest = content.split('\n').map(|x| x.trim_start_matches(prefix).trim_end()).filter(|x| x.len() >= 3 && x.chars().all(|x| x == ':')).map(|x| x.len()).max().unwrap_or(2);
+					let fence = prefix.to_owned() + &":".repeat(longest + 1);
+					lines.push(fence.clone());
+					if !content.is_empty() {
+						lines.push(content);
+					}
+					lines.push(fence);
+				}
+			}
+		};
+		write_rows(&mut lines, head.len());
+		if !specs.is_empty() {
+			let mut line = prefix.to_owned();
+			for (before, end, text) in specs.iter() {
+				// The specification is stretched with dashes instead of tabs
+				let missing = stops[*end].saturating_sub(stops[*before] + text.chars().count());
+				let at = text.find(['-', ':']).map_or(1, |x| x + 1);
+				line += &format!("{}{}{}", &text[..at], "-".repeat(missing), &text[at..]);
+			}
+			lines.push(line + "|");
+		}
+		write_rows(&mut lines, body.len());
+		if !foot.is_empty() {
+			let equals = (stops[width] - start).saturating_sub(1).max(3);
+			lines.push(prefix.to_owned() + "|" + &"=".repeat(equals) + "|");
+			write_rows(&mut lines, foot.len());
+		}
+		lines.join("\n")
+	}
+}
+
+/// A table row that's ready to be lined up.
+struct Row<'a> {
+	/// The cells, with the column before them, the column they end at and their text.
+	cells: Vec<(usize, usize, String)>,
+	attributes: String,
+	/// The blocks of the row's `:::` cells.
+	children: Vec<&'a [Block]>,
+}
+
+/// Blank lines are needed between two tables or two blockquotes, which would be read as one otherwise.
+fn needs_blank(previous: &Block, next: &Block) -> bool {
+	let next = match &next.kind {
+		BlockKind::Figure { content, .. } => &content.kind,
+		kind => kind,
+	};
+	matches!((&previous.kind, next), (BlockKind::Table { .. }, BlockKind::Table { .. }) | (BlockKind::Quote { .. }, BlockKind::Quote { .. }))
+}
+
+/// Two lists of the same kind one after the other are only read as two if there's an attribute sequence between them, which can be empty.
+fn same_list(previous: &Block, next: &Block) -> bool {
+	match (&previous.kind, &next.kind) {
+		(BlockKind::List { ordered: a, .. }, BlockKind::List { ordered: b, .. }) => a == b && next.attributes.is_empty(),
+		_ => false,
+	}
+}
+
+/// How line breaks can be written in a run of inlines.
+#[derive(Clone, Copy)]
+enum Breaks<'a> {
+	/// As `\n`, like inside other inlines and table cells.
+	Escaped,
+	/// Text after a line break that starts with a newline came from a line starting with a space, which continues a paragraph. It's written again like that, after this prefix.
+	Paragraph(&'a str),
+	/// The lines after a list item, footnote or definition continue its text after a line break, so line breaks start a new line after this prefix when they can.
+	Item(&'a str),
+}
+
+/// Where a run of inlines starts, which changes what has to be escaped at its start.
+#[derive(Clone, Copy, PartialEq)]
+enum Position {
+	/// After something else on the same line, like a list marker.
+	Middle,
+	/// At the start of text that's tokenized on its own, like the content of other inlines or of table cells, where `#` starts a header and `|` a table row.
+	Run,
+	/// At the start of a line, where the characters that start blocks have to be escaped too.
+	Line,
+}
+
+fn inlines(inlines: &[Inline], breaks: Breaks, position: Position, nested: bool) -> String {
+	// Tokens that didn't make it leave their text apart from the text around it, but it's all one run of text once it's escaped
+	let mut merged: Vec<Inline> = Vec::new();
+	for inline in inlines {
+		match (merged.last_mut().map(|x| &mut x.kind), &inline.kind) {
+			(Some(InlineKind::Text(text)), InlineKind::Text(next)) => *text += next,
+			_ => merged.push(inline.clone()),
+		}
+	}
+	let inlines = &merged[..];
+	let mut out = String::new();
+	let mut position = position;
+	for (iter, inline) in inlines.iter().enumerate() {
+		let previous = iter.checked_sub(1).map(|x| &inlines[x].kind);
+		let followed = iter + 1 < inlines.len();
+		let here = position;
+		position = Position::Middle;
+		let content = |x: &[Inline]| self::inlines(x, Breaks::Escaped, Position::Run, true);
+		out += &match &inline.kind {
+			InlineKind::Text(text) => {
+				let bracket = matches!(previous, Some(InlineKind::Link { destination: None, .. } | InlineKind::LinkRef { .. } | InlineKind::FootnoteRef { .. }));
+				match (text.strip_prefix('\n'), previous, breaks) {
+					(Some(rest), Some(InlineKind::LineBreak), Breaks::Paragraph(prefix) | Breaks::Item(prefix)) => format!("\n{} {}", prefix, escape(rest, Position::Middle, bracket, followed || nested)),
+					_ => escape(text, here, bracket, followed || nested),
+				}
+			},
+			InlineKind::LineBreak => match (inlines.get(iter + 1).map(|x| &x.kind), breaks) {
+				(Some(InlineKind::Text(text)), Breaks::Paragraph(_) | Breaks::Item(_)) if text.starts_with('\n') => String::new(),
+				(_, Breaks::Item(prefix)) if starts_line(&inlines[iter + 1..]) => {
+					position = Position::Line;
+					"\n".to_owned() + prefix
+				},
+				_ => "\\n".to_owned(),
+			},
+			// An = can't be escaped inside raw text, but it's the same outside of it
+			InlineKind::Raw(text) => text.split('=').map(|x| if x.is_empty() { String::new() } else { format!("\\={}=", x.replace('\\', "\\\\")) }).collect::<Vec<_>>().join("="),
+			InlineKind::Html(html) => escape_html(html),
+			InlineKind::Code(code) => format!("`{}`", code),
+			InlineKind::Image(image) => format!("!{}!", image_source(image)),
+			InlineKind::Link { destination: Some(destination), title, content: name } => {
+				let title = title.as_deref().map(|x| " ".to_owned() + &quote_title(x)).unwrap_or_default();
+				format!("[{}]({}{})", content(name), destination.replace('\\', "\\\\").replace(')', "\\)"), title)
+			},
+			InlineKind::Link { destination: None, content: name, .. } => format!("[{}]", content(name)),
+			InlineKind::LinkRef { label, content: name } => {
+				let label = if *label == plain_text(name) { "" } else { label };
+				format!("[{}][{}]", content(name), label)
+			},
+			InlineKind::FootnoteRef { label, .. } => format!("[^{}]", label),
+			InlineKind::Bold(x) => format!("*{}*", content(x)),
+			InlineKind::Strong(x) => format!("**{}**", content(x)),
+			InlineKind::Italic(x) => format!("_{}_", content(x)),
+			InlineKind::Emphasis(x) => format!("__{}__", content(x)),
+			InlineKind::Sub(x) => format!("~{}~", content(x)),
+			InlineKind::Sup(x) => format!("^{}^", content(x)),
+			InlineKind::Span(x) => format!("@{}@", content(x)),
+			InlineKind::Strike(x) => format!("~~{}~~", content(x)),
+			InlineKind::Under(x) => format!("--{}--", content(x)),
+		};
+		if !matches!(inline.kind, InlineKind::Text(_) | InlineKind::LineBreak) {
+			out += &attributes_after(&inline.attributes);
+		}
+	}
+	out
+}
+
+/// Whether the inlines after a line break in a list item can go on a line of their own and still be read as the item's text.
+fn starts_line(rest: &[Inline]) -> bool {
+	match rest.first().map(|x| &x.kind) {
+		None | Some(InlineKind::LineBreak | InlineKind::Html(_) | InlineKind::Image(_)) => false,
+		Some(InlineKind::Text(text)) => !text.starts_with(char::is_whitespace),
+		_ => true,
+	}
+}
+
+/// Escapes the characters of some text that would mean something to Kami where they are. `bracket` is for text right after a `[...]`, where a `(` would start a link destination and a `:` could make a definition. `followed` is for text with something after it, which a `-` or `!` at its end could join.
+fn escape(text: &str, position: Position, bracket: bool, followed: bool) -> String {
+	let chars: Vec<char> = text.chars().collect();
+	let mut escaped = vec![false; chars.len()];
+	// Backwards, since a - is escaped depending on what comes after it
+	for (iter, cha) in chars.iter().enumerate().rev() {
+		let next = chars.get(iter + 1).copied();
+		escaped[iter] = match cha {
+			'\\' | '*' | '_' | '~' | '^' | '@' | '`' | '[' | ']' | '<' => true,
+			'!' => next.map_or(followed, |x| x != ' '),
+			// A - followed by an escape would keep the backslash of the escape
+			'-' => next.map_or(followed, |x| x == '-' || escaped[iter + 1]),
+			'(' => bracket,
+			'{' => iter == 0,
+			':' => iter == 0 && (bracket || (position == Position::Line && next == Some(' '))),
+			'#' | '|' => iter == 0 && position != Position::Middle,
+			'>' => iter == 0 && position == Position::Line,
+			';' => iter == 0 && position == Position::Line && next == Some(' '),
+			_ => false,
+		};
+	}
+	let mut out = String::new();
+	for (cha, escaped) in chars.into_iter().zip(escaped) {
+		if escaped {
+			out.push('\\');
+		}
+		out.push(cha);
+	}
+	out
+}
+
+/// Escapes what's inside an HTML tag, which would end at its first `>` otherwise.
+fn escape_html(html: &str) -> String {
+	match html.strip_suffix('>') {
+		Some(inner) => inner.replace('\\', "\\\\").replace('>', "\\>") + ">",
+		None => html.to_owned(),
+	}
+}
+
+/// Table rows have their escapes taken out once before their cells are read, so the backslashes of a cell have to be doubled.
+fn double_backslashes(text: &str) -> String {
+	text.replace('\\', "\\\\")
+}
+
+fn attributes_after(attributes: &Attributes) -> String {
+	if attributes.is_empty() { String::new() } else { attributes.to_string() }
+}
+
+/// Writes what goes between the `!` of an image.
+fn image_source(image: &Image) -> String {
+	let escape = |x: &str| x.replace('\\', "\\\\").replace('!', "\\!");
+	let mut candidates = Vec::new();
+	let mut srcset = image.srcset.iter().peekable();
+	match srcset.next_if(|(url, descriptor)| *url == image.source && !descriptor.is_empty()) {
+		Some((url, descriptor)) => candidates.push(escape(url) + " " + descriptor),
+		None => candidates.push(escape(&image.source)),
+	}
+	for (url, descriptor) in srcset {
+		candidates.push(if descriptor.is_empty() { escape(url) } else { escape(url) + " " + descriptor });
+	}
+	let mut out = candidates.join(", ");
+	if image.width.is_some() || image.height.is_some() {
+		let number = |x: Option<u32>| x.map(|x| x.to_string()).unwrap_or_default();
+		out += &format!(" ={}x{}", number(image.width), number(image.height));
+	}
+	out
+}
+
+/// Writes the starter of a table cell like `r2c3*{.class}`. The rowspan and colspan are only taken out of the attributes if they're the last ones, since that's where the starter puts them.
+fn cell_starter(attributes: &Attributes, header: bool) -> String {
+	let mut attributes = attributes.clone();
+	let mut take = |key: &str| match attributes.pairs.last() {
+		Some((k, Some(value))) if k == key && !value.is_empty() && value.chars().all(|x| x.is_ascii_digit()) => {
+			let value = value.to_owned();
+			attributes.pairs.pop();
+			value
+		},
+		_ => String::new(),
+	};
+	let colspan = take("colspan");
+	let rowspan = take("rowspan");
+	let mut out = String::new();
+	if !rowspan.is_empty() {
+		out += &("r".to_owned() + &rowspan);
+	}
+	if !colspan.is_empty() {
+		out += &("c".to_owned() + &colspan);
+	}
+	if header {
+		out.push('*');
+	}
+	out + &attributes_after(&attributes)
+}
+
+/// Writes the destination of a link definition, between `<` and `>` if it has spaces or would be read as something else.
+fn destination(destination: &str) -> String {
+	if destination.is_empty() || destination.starts_with('<') || destination.contains(|x: char| x.is_whitespace() || x == '{') {
+		format!("<{}>", destination)
+	} else {
+		destination.to_owned()
+	}
+}
+
+fn quote_title(title: &str) -> String {
+	if !title.contains('"') {
+		format!("\"{}\"", title)
+	} else if !title.contains('\'') {
+		format!("'{}'", title)
+	} else {
+		format!("({})", title)
+	}
+}
+
+/// Where the line with `pos` in it ends, before its `\r\n` or `\n`.
+fn line_end(input: &str, pos: usize) -> usize {
+	let end = input[pos..].find('\n').map_or(input.len(), |x| pos + x);
+	if input[pos..end].ends_with('\r') { end - 1 } else { end }
+}
+
+fn skip_blank_lines(text: &str) -> &str {
+	let mut rest = text;
+	while let Some((line, after)) = rest.split_once('\n') {
+		if !line.trim().is_empty() {
+			break;
+		}
+		rest = after;
+	}
+	rest
+}
@@ -0,0 +1,352 @@
+use crate::ast::{plain_text, Alignment, Attributes, Block, DefinitionEntry, Document, Footnote, Image, Inline, ListItem, TableColumn, TableRow, TocEntry};
+use crate::render::Renderer;
+use crate::syntax::Options;
+use htmlentity::entity::*;
+
+pub fn render(document: &Document) -> String {
+	render_with(document, &Options::default())
+}
+
+pub fn render_with(document: &Document, options: &Options) -> String {
+	HtmlRenderer::new(options).document(document)
+}
+
+/// Renders documents as HTML, with the default methods of `Renderer`.
+pub struct HtmlRenderer<'a> {
+	options: &'a Options,
+}
+
+impl<'a> HtmlRenderer<'a> {
+	pub fn new(options: &'a Options) -> Self {
+		Self { options }
+	}
+}
+
+impl Renderer for HtmlRenderer<'_> {
+	fn options(&self) -> &Options {
+		self.options
+	}
+}
+
+/// Renders the footnotes in a notes section, each with a link back to every one of its references.
+pub(crate) fn footnotes<R: Renderer + ?Sized>(r: &mut R, notes: &[Footnote]) -> String {
+	if notes.is_empty() {
+		return String::new()
+	}
+	let mut out = "<section class=\"footnotes\">\n<ol>\n".to_owned();
+	for note in notes.iter() {
+		let mut attributes = note.attributes.clone();
+		attributes.id = Some(format!("fn-{}", note.number));
+		out += &("<li ".to_owned() + &block_attr(&attributes, note.line, r.options()) + ">" + &r.inlines(&note.content));
+		for reference in 1..=note.references {
+			out += &format!(" <a href=\"#{}\" class=\"footnote-back\">&#8617;</a>", footnote_ref_id(note.number, reference));
+		}
+		if !note.children.is_empty() {
+			out += &("\n".to_owned() + &r.blocks(&note.children));
+		}
+		out += "</li>\n";
+	}
+	out + "</ol>\n</section>\n"
+}
+
+/// The first reference to a footnote gets the id `fnref-N`, and the rest `fnref-N-2`, `fnref-N-3` and so on.
+pub(crate) fn footnote_ref_id(number: usize, reference: usize) -> String {
+	if reference <= 1 {
+		format!("fnref-{}", number)
+	} else {
+		format!("fnref-{}-{}", number, reference)
+	}
+}
+
+/// Wraps rendered HTML in a full HTML page.
+pub fn standalone(title: &str, body: &str) -> String {
+	"<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>".to_owned() + &encode_text(title) + "</title>\n</head>\n<body>\n" + body + "</body>\n</html>\n"
+}
+
+pub fn render_attr(attributes: &Attributes) -> String {
+	let mut out: Vec<String> = Vec::new();
+	if let Some(id) = &attributes.id {
+		out.push("id=\"".to_owned() + &encode_attr(id) + "\"");
+	}
+	if !attributes.classes.is_empty() {
+		out.push("class=\"".to_owned() + &encode_attr(&attributes.classes.join(" ")) + "\"");
+	}
+	for (key, value) in attributes.pairs.iter() {
+		match value {
+			Some(value) => out.push(key.to_owned() + "=\"" + &encode_attr(value) + "\""),
+			None => out.push(key.to_owned()),
+		}
+	}
+	out.join(" ")
+}
+
+/// Renders attributes, leaving out the ones that safe mode doesn't allow.
+fn safe_attr(attributes: &Attributes, options: &Options) -> String {
+	match &options.safe_mode {
+		Some(safe) => render_attr(&safe.attributes(attributes)),
+		None => render_attr(attributes),
+	}
+}
+
+/// Renders a link or image destination, or nothing if safe mode doesn't allow it.
+fn safe_url(attribute: &str, url: &str, image: bool, options: &Options) -> String {
+	match &options.safe_mode {
+		Some(safe) if !safe.allows_url(url, image) => String::new(),
+		_ => attribute.to_owned() + "=\"" + &encode_attr(&escape_url(url)) + "\"",
+	}
+}
+
+/// Renders raw HTML, which safe mode either filters, escapes or drops.
+pub(crate) fn safe_html(html: &str, tag: bool, options: &Options) -> String {
+	match &options.safe_mode {
+		Some(safe) => match tag.then(|| safe.html_tag(html)).flatten() {
+			Some(tag) => tag,
+			None => safe.disallowed_html(html),
+		},
+		None => html.to_owned(),
+	}
+}
+
+/// Renders the attributes of a block level element, adding its source line if the options ask for it.
+fn block_attr(attributes: &Attributes, line: usize, options: &Options) -> String {
+	let mut out = safe_attr(attributes, options);
+	if options.source_lines {
+		if !out.is_empty() {
+			out += " ";
+		}
+		out += &format!("data-line=\"{}\"", line);
+	}
+	out
+}
+
+pub(crate) fn paragraph<R: Renderer + ?Sized>(r: &mut R, block: &Block, content: &[Inline]) -> String {
+	let attr = block_attr(&block.attributes, block.line, r.options());
+	"<p ".to_owned() + &attr + ">" + &r.inlines(content) + "</p>\n"
+}
+
+pub(crate) fn header<R: Renderer + ?Sized>(r: &mut R, block: &Block, level: usize, content: &[Inline]) -> String {
+	let attr = block_attr(&block.attributes, block.line, r.options());
+	"<h".to_owned() + &level.to_string() + " " + &attr + ">" + &r.inlines(content) + "</h" + &level.to_string() + ">\n"
+}
+
+pub(crate) fn html_block<R: Renderer + ?Sized>(r: &mut R, tag: &str, content: &[Inline]) -> String {
+	safe_html(tag, true, r.options()) + &r.inlines(content) + "\n"
+}
+
+pub(crate) fn code_block<R: Renderer + ?Sized>(r: &mut R, block: &Block, language: Option<&str>, content: &str) -> String {
+	let attr = block_attr(&block.attributes, block.line, r.options());
+	let class = match language {
+		Some(language) => " class=\"language-".to_owned() + &encode_attr(language) + "\"",
+		None => String::new(),
+	};
+	"<pre ".to_owned() + &attr + "><code" + &class + ">" + &encode_text(content) + "</code></pre>\n"
+}
+
+pub(crate) fn quote<R: Renderer + ?Sized>(r: &mut R, block: &Block, content: &[Block], attribution: Option<&[Inline]>) -> String {
+	let attr = block_attr(&block.attributes, block.line, r.options());
+	let out = "<blockquote ".to_owned() + &attr + ">\n" + &r.blocks(content) + "</blockquote>\n";
+	match attribution {
+		Some(attribution) => "<figure>\n".to_owned() + &out + "<figcaption>" + &r.inlines(attribution) + "</figcaption>\n</figure>\n",
+		None => out,
+	}
+}
+
+/// Renders a table. Tables without a head or foot don't get a `<tbody>` either.
+pub(crate) fn table<R: Renderer + ?Sized>(r: &mut R, block: &Block, caption: Option<&[Inline]>, columns: &[TableColumn], [head, body, foot]: [&[TableRow]; 3]) -> String {
+	let mut out = "<table ".to_owned() + &block_attr(&block.attributes, block.line, r.options()) + ">\n";
+	if let Some(caption) = caption {
+		out += &("<caption>".to_owned() + &r.inlines(caption) + "</caption>\n");
+	}
+	if columns.iter().any(|x| !x.attributes.is_empty()) {
+		out += "<colgroup>\n";
+		for column in columns.iter() {
+			out += &("<col ".to_owned() + &safe_attr(&column.attributes, r.options()) + ">\n");
+		}
+		out += "</colgroup>\n";
+	}
+	if head.is_empty() && foot.is_empty() {
+		return out + &rows(r, body) + "</table>\n"
+	}
+	for (tag, section) in [("thead", head), ("tbody", body), ("tfoot", foot)] {
+		if !section.is_empty() {
+			out += &("<".to_owned() + tag + ">\n" + &rows(r, section) + "</" + tag + ">\n");
+		}
+	}
+	out + "</table>\n"
+}
+
+fn rows<R: Renderer + ?Sized>(r: &mut R, rows: &[TableRow]) -> String {
+	let mut out = String::new();
+	for row in rows.iter() {
+		out += &("<tr ".to_owned() + &block_attr(&row.attributes, row.line, r.options()) + ">\n");
+		for cell in row.cells.iter() {
+			let htag = if cell.header { "th" } else { "td" };
+			let mut attributes = match &r.options().safe_mode {
+				Some(safe) => safe.attributes(&cell.attributes),
+				None => cell.attributes.clone(),
+			};
+			// The alignment is added after safe mode, which would otherwise drop it with the rest of the styles
+			if let Some(align) = cell.align {
+				let align = match align {
+					Alignment::Left => "left",
+					Alignment::Center => "center",
+					Alignment::Right => "right",
+				};
+				let style = match attributes.get("style") {
+					Some(style) => style.trim_end().trim_end_matches(';').to_owned() + "; text-align: " + align,
+					None => "text-align: ".to_owned() + align,
+				};
+				attributes.set("style", Some(&style));
+			}
+			out += &("<".to_owned() + htag + " " + &render_attr(&attributes) + ">");
+			out += &r.inlines(&cell.content);
+			if !cell.children.is_empty() {
+				out += &("\n".to_owned() + &r.blocks(&cell.children));
+			}
+			out += &("</".to_owned() + htag + ">\n");
+		}
+		out += "</tr>\n";
+	}
+	out
+}
+
+pub(crate) fn figure<R: Renderer + ?Sized>(r: &mut R, block: &Block, number: usize, content: &Block, caption: &[Inline]) -> String {
+	let attr = block_attr(&block.attributes, block.line, r.options());
+	let label = if caption.is_empty() { format!("Figure {}", number) } else { format!("Figure {}: ", number) };
+	"<figure ".to_owned() + &attr + ">\n" + &r.block(content) + "<figcaption>" + &label + &r.inlines(caption) + "</figcaption>\n</figure>\n"
+}
+
+pub(crate) fn definition_list<R: Renderer + ?Sized>(r: &mut R, block: &Block, entries: &[DefinitionEntry]) -> String {
+	let mut out = "<dl ".to_owned() + &block_attr(&block.attributes, block.line, r.options()) + ">\n";
+	for entry in entries.iter() {
+		for term in entry.terms.iter() {
+			out += &list_item(r, "dt", term);
+		}
+		for definition in entry.definitions.iter() {
+			out += &list_item(r, "dd", definition);
+		}
+	}
+	out + "</dl>\n"
+}
+
+pub(crate) fn list<R: Renderer + ?Sized>(r: &mut R, block: &Block, ordered: bool, items: &[ListItem]) -> String {
+	let tag = if ordered { "ol" } else { "ul" };
+	let mut out = "<".to_owned() + tag + " " + &block_attr(&block.attributes, block.line, r.options()) + ">\n";
+	for item in items.iter() {
+		out += &list_item(r, "li", item);
+	}
+	out + "</" + tag + ">\n"
+}
+
+/// Renders a list item, or a term or definition of a definition list.
+fn list_item<R: Renderer + ?Sized>(r: &mut R, tag: &str, item: &ListItem) -> String {
+	let mut out = "<".to_owned() + tag + " " + &block_attr(&item.attributes, item.line, r.options()) + ">" + &r.inlines(&item.content);
+	if !item.children.is_empty() {
+		out += &("\n".to_owned() + &r.blocks(&item.children));
+	}
+	out + "</" + tag + ">\n"
+}
+
+pub(crate) fn toc<R: Renderer + ?Sized>(r: &mut R, block: &Block, entries: &[TocEntry]) -> String {
+	"<nav ".to_owned() + &block_attr(&block.attributes, block.line, r.options()) + ">\n" + &toc_entries(entries) + "</nav>\n"
+}
+
+fn toc_entries(entries: &[TocEntry]) -> String {
+	let mut out = "<ul>\n".to_owned();
+	for entry in entries.iter() {
+		out += "<li>";
+		out += &match &entry.id {
+			Some(id) => "<a href=\"#".to_owned() + &encode_attr(id) + "\">" + &encode_text(&entry.title) + "</a>",
+			None => encode_text(&entry.title),
+		};
+		if !entry.children.is_empty() {
+			out += "\n";
+			out += &toc_entries(&entry.children);
+		}
+		out += "</li>\n";
+	}
+	out + "</ul>\n"
+}
+
+/// Renders an image. Its size goes in its attributes, and so does `loading="lazy"` if the options ask for it, unless the attributes already have them. `line` is the source line of images that are blocks of their own.
+pub(crate) fn image<R: Renderer + ?Sized>(r: &mut R, image: &Image, attributes: &Attributes, line: Option<usize>) -> String {
+	let options = r.options();
+	let mut attributes = attributes.clone();
+	for (key, value) in [("width", image.width), ("height", image.height)] {
+		if let Some(value) = value.filter(|_| !attributes.has(key)) {
+			attributes.set(key, Some(&value.to_string()));
+		}
+	}
+	if options.lazy_images && !attributes.has("loading") {
+		attributes.set("loading", Some("lazy"));
+	}
+	let attr = match line {
+		Some(line) => block_attr(&attributes, line, options),
+		None => safe_attr(&attributes, options),
+	};
+	let srcset: Vec<String> = image.srcset.iter()
+		.filter(|(url, _)| options.safe_mode.as_ref().is_none_or(|x| x.allows_url(url, true)))
+		.map(|(url, descriptor)| if descriptor.is_empty() { escape_url(url) } else { escape_url(url) + " " + descriptor })
+		.collect();
+	let srcset = if srcset.is_empty() { String::new() } else { " srcset=\"".to_owned() + &encode_attr(&srcset.join(", ")) + "\"" };
+	"<img ".to_owned() + &attr + " " + &safe_url("src", &image.source, true, options) + &srcset + "/>"
+}
+
+pub(crate) fn code<R: Renderer + ?Sized>(r: &mut R, inline: &Inline, text: &str) -> String {
+	"<code ".to_owned() + &safe_attr(&inline.attributes, r.options()) + ">" + &encode_text(text) + "</code>"
+}
+
+pub(crate) fn link<R: Renderer + ?Sized>(r: &mut R, inline: &Inline, destination: Option<&str>, title: Option<&str>, content: &[Inline]) -> String {
+	let href = match destination {
+		Some(destination) => destination.to_owned(),
+		None => plain_text(content),
+	};
+	let title = match title {
+		Some(title) => " title=\"".to_owned() + &encode_attr(title) + "\"",
+		None => String::new(),
+	};
+	let start = "<a ".to_owned() + &safe_url("href", &href, false, r.options()) + &title + " " + &safe_attr(&inline.attributes, r.options()) + ">";
+	start + &r.inlines(content) + "</a>"
+}
+
+pub(crate) fn footnote_ref(label: &str, number: Option<usize>, reference: usize) -> String {
+	match number {
+		Some(number) => format!("<sup id=\"{}\"><a href=\"#fn-{}\" class=\"footnote-ref\">{}</a></sup>", footnote_ref_id(number, reference), number, number),
+		None => encode_text(&("[^".to_owned() + label + "]")),
+	}
+}
+
+/// Renders inline content inside an element like `<b>`.
+pub(crate) fn wrap<R: Renderer + ?Sized>(r: &mut R, tag: &str, inline: &Inline, content: &[Inline]) -> String {
+	let attr = safe_attr(&inline.attributes, r.options());
+	"<".to_owned() + tag + " " + &attr + ">" + &r.inlines(content) + "</" + tag + ">"
+}
+
+pub fn render_inlines(input: &[Inline]) -> String {
+	HtmlRenderer::new(&Options::default()).inlines(input)
+}
+
+pub fn encode_text(text: &str) -> String {
+	encode(text, EntitySet::SpecialCharsAndNoASCII, EncodeType::NamedOrHex).iter().collect::<String>()
+}
+
+/// Percent-encodes the characters that can't appear in a URL, like spaces and non-ASCII letters. Existing escapes like `%20` are kept as they are.
+pub fn escape_url(url: &str) -> String {
+	let mut out = String::new();
+	for cha in url.chars() {
+		if cha.is_ascii_graphic() && !matches!(cha, '"' | '<' | '>' | '`' | '{' | '}' | '|' | '\\' | '^') {
+			out.push(cha);
+		} else {
+			let mut bytes = [0; 4];
+			for byte in cha.encode_utf8(&mut bytes).bytes() {
+				out += &format!("%{:02X}", byte);
+			}
+		}
+	}
+	out
+}
+
+/// Escapes an attribute value so it can go between double quotes.
+pub fn encode_attr(text: &str) -> String {
+	encode(text, EntitySet::SpecialChars, EncodeType::NamedOrHex).iter().collect::<String>()
+}
@@ -0,0 +1,66 @@
+use crate::ast::Image;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// Called with the source of every image in a document, including the other versions in its srcset. Returns the source to use instead, like the address of the file in a CDN, or why the image can't be used, which is reported as a warning.
+pub type ImageResolver = dyn Fn(&str) -> Result<String, String> + Send + Sync;
+
+/// Reads what's between the `!` of an image, like `photo.png, photo-2x.png 2x =640x480`. The first file is the source of the image, and every file with a descriptor like `2x` or `800w` goes in its srcset. A last `=WIDTHxHEIGHT` gives its size, and either number can be left out.
+pub(crate) fn parse_image(inner: &str) -> Image {
+	let mut out = Image::default();
+	let mut inner = inner.trim();
+	if let Some((rest, last)) = inner.rsplit_once(char::is_whitespace) {
+		if let Some((width, height)) = last.strip_prefix('=').and_then(size) {
+			out.width = width;
+			out.height = height;
+			inner = rest.trim_end();
+		}
+	}
+	// Only a comma followed by a space separates files, so that data: URLs stay whole
+	for (iter, candidate) in inner.split(", ").enumerate() {
+		let candidate = candidate.trim();
+		let (url, descriptor) = match candidate.rsplit_once(char::is_whitespace) {
+			Some((url, descriptor)) if is_descriptor(descriptor) => (url.trim_end(), descriptor),
+			_ => (candidate, ""),
+		};
+		if iter == 0 {
+			out.source = url.to_owned();
+		}
+		if iter > 0 || !descriptor.is_empty() {
+			out.srcset.push((url.to_owned(), descriptor.to_owned()));
+		}
+	}
+	out
+}
+
+/// Reads a size like `640x480`, `640x`, `x480` or `640`.
+fn size(spec: &str) -> Option<(Option<u32>, Option<u32>)> {
+	let (width, height) = spec.split_once('x').unwrap_or((spec, ""));
+	let number = |x: &str| if x.is_empty() { Some(None) } else { x.parse::<u32>().ok().map(Some) };
+	match (number(width)?, number(height)?) {
+		(None, None) => None,
+		size => Some(size),
+	}
+}
+
+/// Descriptors of srcset candidates are a density like `2x` or `1.5x`, or a width like `800w`.
+fn is_descriptor(text: &str) -> bool {
+	match text.strip_suffix('x') {
+		Some(density) => density.parse::<f32>().is_ok_and(|x| x > 0.0),
+		None => text.strip_suffix('w').is_some_and(|x| x.parse::<u32>().is_ok()),
+	}
+}
+
+/// A resolver that checks that local images exist, relative to `dir`. Sources with a scheme like `https:`, and absolute paths, are left alone since they don't point to files next to the document.
+pub fn local_files(dir: impl Into<PathBuf>) -> Arc<ImageResolver> {
+	let dir = dir.into();
+	Arc::new(move |source: &str| {
+		let path = source.split(['?', '#']).next().unwrap_or_default();
+		let remote = path.starts_with('/') || path.split_once(':').is_some_and(|(scheme, _)| !scheme.is_empty() && scheme.chars().all(|x| x.is_ascii_alphanumeric() || matches!(x, '+' | '-' | '.')));
+		if remote || dir.join(path).exists() {
+			Ok(source.to_owned())
+		} else {
+			Err(format!("Image {} doesn't exist", dir.join(path).display()))
+		}
+	})
+}
@@ -0,0 +1,1056 @@
+use crate::ast::Alignment;
+use crate::attributes::Attributes;
+use crate::diagnostic::Diagnostic;
+use htmlentity::entity::decode;
+use std::ops::Range;
+
+/// A line of the Markdown source, with its position in it.
+type Line<'a> = (usize, &'a str);
+
+/// Tags that start an HTML block, which goes on until the next blank line.
+const BLOCK_TAGS: &[&str] = &[
+	"address", "article", "aside", "blockquote", "body", "caption", "center", "col", "colgroup", "dd", "details", "dialog", "dir", "div", "dl", "dt",
+	"fieldset", "figcaption", "figure", "footer", "form", "h1", "h2", "h3", "h4", "h5", "h6", "head", "header", "hr", "html", "iframe", "legend", "li",
+	"link", "main", "menu", "nav", "ol", "p", "param", "section", "summary", "table", "tbody", "td", "tfoot", "th", "thead", "title", "tr", "ul",
+];
+
+/// Blockquotes, list items and footnotes are read recursively, so their nesting is limited to keep deep ones from overflowing the stack.
+const MAX_DEPTH: usize = 32;
+
+/// Tags whose HTML block goes on until they're closed, even across blank lines.
+const RAW_TAGS: &[&str] = &["pre", "script", "style", "textarea"];
+
+/// Converts a CommonMark document, with the GitHub extensions for tables, strikethrough and footnotes, into Kami. Also returns a warning for every part of it that couldn't be translated, pointing into the Markdown source.
+///
+/// Emphasis becomes Kami's emphasis (`__`) and strong emphasis becomes strong (`**`), since that's what Markdown renders them as. Nested lists are written with repeated `*` and `#.` markers, and the blocks of list items that don't fit on their first line are indented under them.
+pub fn markdown(input: &str) -> (String, Vec<Diagnostic>) {
+	let mut lines: Vec<Line> = Vec::new();
+	let mut offset = 0;
+	for line in input.split_inclusive('\n') {
+		lines.push((offset, line.trim_end_matches(['\n', '\r'])));
+		offset += line.len();
+	}
+	let mut importer = Importer { definitions: Vec::new(), footnotes: Vec::new(), warnings: Vec::new(), depth: 0 };
+	let blocks = importer.blocks(&lines);
+	let mut out = importer.write_blocks(&blocks, 1);
+	if !out.is_empty() {
+		out.push('\n');
+	}
+	let mut warnings = importer.warnings;
+	warnings.sort_by_key(|x| x.span.start);
+	for warning in warnings.iter_mut() {
+		warning.locate(input);
+	}
+	(out, warnings)
+}
+
+enum Block<'a> {
+	/// The lines of a paragraph, or of a header when it has a level.
+	Text { level: Option<usize>, lines: Vec<&'a str> },
+	/// A code block, with the position of its info string.
+	Code { info: &'a str, content: String, offset: usize },
+	Quote(Vec<Block<'a>>),
+	List { ordered: bool, start: u64, items: Vec<Item<'a>>, span: Range<usize> },
+	Table { align: Vec<Option<Alignment>>, rows: Vec<Vec<String>> },
+	Html(Vec<&'a str>),
+	Rule,
+	Footnote { label: &'a str, content: Vec<Block<'a>> },
+	Definition { label: &'a str, destination: String, title: Option<String> },
+}
+
+struct Item<'a> {
+	checkbox: bool,
+	content: Vec<Block<'a>>,
+	span: Range<usize>,
+}
+
+/// What a list marker like `- ` or `12) ` says about its list.
+struct Marker {
+	ordered: bool,
+	/// The bullet, or the `.` or `)` after the number. Items with a different one start a new list.
+	delimiter: char,
+	start: u64,
+	/// The column the item's content starts at, which its other lines have to be indented to.
+	width: usize,
+	/// Where the content starts in the line.
+	content: usize,
+	empty: bool,
+}
+
+struct Importer {
+	/// Every link reference definition: its normalized label, its label as written, its destination and its title.
+	definitions: Vec<(String, String, String, Option<String>)>,
+	/// The normalized labels of every footnote.
+	footnotes: Vec<String>,
+	warnings: Vec<Diagnostic>,
+	/// How many blocks the lines being read are nested in.
+	depth: usize,
+}
+
+impl Importer {
+	fn untranslated(&mut self, message: &str, span: Range<usize>) {
+		self.warnings.push(Diagnostic::warning("untranslated-markdown", message.to_owned(), span));
+	}
+
+	/// Reads the lines inside a blockquote, a list item or a footnote.
+	fn nested<'a>(&mut self, lines: &[Line<'a>]) -> Vec<Block<'a>> {
+		if self.depth >= MAX_DEPTH {
+			let span = lines.first().map_or(0, |x| x.0)..lines.last().map_or(0, |x| x.0 + x.1.len());
+			self.untranslated(&format!("Blocks can't be nested more than {} times, so these lines were kept as text", MAX_DEPTH), span);
+			return vec![Block::Text { level: None, lines: lines.iter().map(|x| x.1.trim()).filter(|x| !x.is_empty()).collect() }]
+		}
+		self.depth += 1;
+		let out = self.blocks(lines);
+		self.depth -= 1;
+		out
+	}
+
+	fn blocks<'a>(&mut self, lines: &[Line<'a>]) -> Vec<Block<'a>> {
+		let mut out: Vec<Block> = Vec::new();
+		let mut iter = 0;
+		while iter < lines.len() {
+			let (offset, line) = lines[iter];
+			if line.trim().is_empty() {
+				iter += 1;
+				continue;
+			}
+			let indent = indentation(line);
+			let rest = line.trim_start();
+			if indent >= 4 {
+				// Indented code goes on until a line that isn't indented, without the blank lines at its end
+				let mut end = iter;
+				while end < lines.len() && (lines[end].1.trim().is_empty() || indentation(lines[end].1) >= 4) {
+					end += 1;
+				}
+				while lines[end - 1].1.trim().is_empty() {
+					end -= 1;
+				}
+				let content: String = lines[iter..end].iter().map(|(_, x)| strip_indent(x, 4).to_owned() + "\n").collect();
+				out.push(Block::Code { info: "", content, offset });
+				iter = end;
+			} else if let Some((cha, len)) = fence(rest) {
+				let info = rest[len..].trim();
+				let info_offset = offset + line.len() - rest[len..].trim_start().len();
+				let mut content = String::new();
+				iter += 1;
+				while iter < lines.len() {
+					let text = lines[iter].1;
+					if indentation(text) < 4 && fence(text.trim_start()).is_some_and(|(x, n)| x == cha && n >= len && text.trim_start()[n..].trim().is_empty()) {
+						iter += 1;
+						break;
+					}
+					content += strip_indent(text, indent);
+					content.push('\n');
+					iter += 1;
+				}
+				out.push(Block::Code { info, content, offset: info_offset });
+			} else if let Some(level) = atx_header(rest) {
+				let text = rest[level..].trim();
+				// A closing sequence of #s is left out, as long as there's a space before it
+				let trimmed = text.trim_end_matches('#');
+				let text = if trimmed.is_empty() || trimmed.ends_with(' ') { trimmed.trim_end() } else { text };
+				out.push(Block::Text { level: Some(level), lines: vec![text] });
+				iter += 1;
+			} else if is_rule(rest) {
+				out.push(Block::Rule);
+				iter += 1;
+			} else if rest.starts_with('>') {
+				let mut inner: Vec<Line> = Vec::new();
+				while iter < lines.len() {
+					let (offset, text) = lines[iter];
+					let rest = text.trim_start();
+					if indentation(text) < 4 && rest.starts_with('>') {
+						let stripped = rest[1..].strip_prefix(' ').unwrap_or(&rest[1..]);
+						inner.push((offset + text.len() - stripped.len(), stripped));
+					} else if !text.trim().is_empty() && inner.last().is_some_and(|x| is_lazy(x.1)) && !starts_block(text) {
+						inner.push((offset, text));
+					} else {
+						break;
+					}
+					iter += 1;
+				}
+				out.push(Block::Quote(self.nested(&inner)));
+			} else if let Some(marker) = list_marker(line) {
+				iter = self.list(lines, iter, marker, &mut out);
+			} else if let Some(end) = html_block(lines, iter) {
+				out.push(Block::Html(lines[iter..end].iter().map(|x| x.1).collect()));
+				iter = end;
+			} else if let Some((label, content)) = footnote_definition(rest) {
+				let mut inner: Vec<Line> = vec![(offset + line.len() - content.len(), content)];
+				iter += 1;
+				while iter < lines.len() {
+					let (offset, text) = lines[iter];
+					if text.trim().is_empty() || indentation(text) >= 4 {
+						let stripped = strip_indent(text, 4);
+						inner.push((offset + text.len() - stripped.len(), stripped));
+					} else if inner.last().is_some_and(|x| is_lazy(x.1)) && !starts_block(text) {
+						inner.push((offset, text));
+					} else {
+						break;
+					}
+					iter += 1;
+				}
+				self.footnotes.push(normalize(label));
+				out.push(Block::Footnote { label, content: self.nested(&inner) });
+			} else if let Some((label, destination, title)) = link_definition(rest) {
+				self.definitions.push((normalize(label), label.to_owned(), destination.clone(), title.clone()));
+				out.push(Block::Definition { label, destination, title });
+				iter += 1;
+			} else if let Some(end) = table(lines, iter) {
+				let align = split_row(lines[iter + 1].1).iter().map(|x| column_alignment(x)).collect::<Vec<_>>();
+				let mut rows = vec![split_row(line)];
+				rows.extend(lines[iter + 2..end].iter().map(|x| split_row(x.1)));
+				for row in rows.iter_mut() {
+					row.resize(align.len(), String::new());
+				}
+				out.push(Block::Table { align, rows });
+				iter = end;
+			} else {
+				// A paragraph goes on until a blank line or a line that starts another block, unless it's the underline of a setext header
+				let mut text = vec![rest];
+				let mut level = None;
+				iter += 1;
+				while iter < lines.len() {
+					let next = lines[iter].1;
+					if next.trim().is_empty() {
+						break;
+					}
+					if indentation(next) < 4 {
+						let underline = next.trim();
+						if underline.chars().all(|x| x == '=') {
+							level = Some(1);
+						} else if underline.chars().all(|x| x == '-') {
+							level = Some(2);
+						}
+					}
+					if level.is_some() {
+						iter += 1;
+						break;
+					}
+					if starts_block(next) || table(lines, iter).is_some() {
+						break;
+					}
+					text.push(next.trim_start());
+					iter += 1;
+				}
+				out.push(Block::Text { level, lines: text });
+			}
+		}
+		out
+	}
+
+	/// Reads the list starting at `start`, and returns where it ends.
+	fn list<'a>(&mut self, lines: &[Line<'a>], start: usize, first: Marker, out: &mut Vec<Block<'a>>) -> usize {
+		let mut items: Vec<Item> = Vec::new();
+		let mut iter = start;
+		while iter < lines.len() {
+			let (offset, line) = lines[iter];
+			let Some(marker) = list_marker(line) else { break };
+			if marker.ordered != first.ordered || marker.delimiter != first.delimiter {
+				break;
+			}
+			let content = &line[marker.content..];
+			let checkbox = ["[ ] ", "[x] ", "[X] "].iter().any(|x| content.starts_with(x));
+			let mut inner: Vec<Line> = vec![(offset + line.len() - content.len(), content)];
+			iter += 1;
+			while iter < lines.len() {
+				let (offset, text) = lines[iter];
+				if text.trim().is_empty() {
+					// A blank line after an empty item ends it
+					if inner.len() == 1 && content.is_empty() {
+						break;
+					}
+					inner.push((offset, ""));
+				} else if indentation(text) >= marker.width {
+					let stripped = strip_indent(text, marker.width);
+					inner.push((offset + text.len() - stripped.len(), stripped));
+				} else if list_marker(text).is_some() {
+					break;
+				} else if inner.last().is_some_and(|x| is_lazy(x.1)) && !starts_block(text) {
+					inner.push((offset, text));
+				} else {
+					break;
+				}
+				iter += 1;
+			}
+			// The blank lines after the item are between it and the next one
+			while inner.last().is_some_and(|x| x.1.trim().is_empty()) && inner.len() > 1 {
+				inner.pop();
+			}
+			let end = inner.last().map(|x| x.0 + x.1.len()).unwrap_or(offset);
+			items.push(Item { checkbox, content: self.nested(&inner), span: offset..end });
+			while iter < lines.len() && lines[iter].1.trim().is_empty() && lines.get(iter + 1).is_some_and(|x| list_marker(x.1).is_some_and(|x| x.ordered == first.ordered && x.delimiter == first.delimiter)) {
+				iter += 1;
+			}
+		}
+		let span = lines[start].0..items.last().map(|x| x.span.end).unwrap_or(lines[start].0);
+		out.push(Block::List { ordered: first.ordered, start: first.start, items, span });
+		iter
+	}
+
+	/// Writes blocks as Kami, separated by blank lines. `depth` is how deep the lists among them are.
+	fn write_blocks(&mut self, blocks: &[Block], depth: usize) -> String {
+		let mut out: Vec<String> = Vec::new();
+		for block in blocks.iter() {
+			out.push(self.write_block(block, depth));
+		}
+		out.join("\n\n")
+	}
+
+	fn write_block(&mut self, block: &Block, depth: usize) -> String {
+		match block {
+			Block::Text { level: None, lines } => {
+				let text = self.inlines(&lines.join("\n"));
+				let text = match text.chars().next() {
+					// A line starting with HTML wouldn't be a paragraph
+					Some('<') => "{} ".to_owned() + &text,
+					Some('#' | '>' | '|' | ';' | ':') => "\\".to_owned() + &text,
+					_ => text,
+				};
+				text.replace('\n', "\n ")
+			},
+			Block::Text { level: Some(level), lines } => "#".repeat(*level) + " " + &self.inlines(&lines.join("\n")).replace('\n', " "),
+			Block::Code { info, content, offset } => {
+				let (language, rest) = info.split_once(char::is_whitespace).unwrap_or((info, ""));
+				// Kami closes code blocks with any line of at least as many backticks
+				let longest = content.lines().filter(|x| !x.is_empty() && x.trim_end().chars().all(|x| x == '`')).map(|x| x.trim_end().len()).max().unwrap_or(0);
+				let fence = "`".repeat(longest.max(2) + 1);
+				let out = fence.clone() + &unescape(language);
+				if !rest.trim().is_empty() {
+					self.untranslated("Kami code blocks only have a language, so the rest of the info string was left out", *offset..offset + info.len());
+				}
+				out + "\n" + content + &fence
+			},
+			Block::Quote(content) => {
+				let inner = self.write_blocks(content, 1);
+				let lines: Vec<String> = inner.lines().map(|x| if x.is_empty() { ">".to_owned() } else { "> ".to_owned() + x }).collect();
+				lines.join("\n")
+			},
+			Block::List { ordered, start, items, span } => {
+				let mut out = String::new();
+				if *ordered && *start != 1 {
+					if depth == 1 {
+						out += &format!("{{start=\"{}\"}}\n", start);
+					} else {
+						self.untranslated("Kami can't give a start number to nested lists, so this one starts at 1", span.clone());
+					}
+				}
+				out + &self.write_list(*ordered, items, depth)
+			},
+			Block::Table { align, rows } => {
+				let mut out: Vec<String> = Vec::new();
+				for (iter, row) in rows.iter().enumerate() {
+					let mut line = "|".to_owned();
+					for cell in row.iter() {
+						// Table cells can't have a |, but raw HTML can have its entity. Kami reads escapes in table rows once before reading the cells, so they have to be doubled
+						let content = self.inlines(cell).replace('|', "\\=&#124;=").replace('\\', "\\\\");
+						let content = if content == ":::" { "\\\\:::".to_owned() } else { content };
+						line += &(" ".to_owned() + &content + " |");
+					}
+					out.push(line);
+					if iter == 0 {
+						let spec: Vec<&str> = align.iter().map(|x| match x {
+							Some(Alignment::Left) => ":---",
+							Some(Alignment::Center) => ":---:",
+							Some(Alignment::Right) => "---:",
+							None => "---",
+						}).collect();
+						out.push("|".to_owned() + &spec.join("|") + "|");
+					}
+				}
+				out.join("\n")
+			},
+			Block::Html(lines) => {
+				let lines: Vec<String> = lines.iter().map(|x| html_line(x.trim_start())).collect();
+				lines.join("\n")
+			},
+			Block::Rule => "<hr>".to_owned(),
+			Block::Footnote { label, content } => self.write_item(&format!("[^{}]: ", label), content, None),
+			Block::Definition { label, destination, title } => {
+				let destination = if destination.is_empty() || destination.contains(char::is_whitespace) { "<".to_owned() + destination + ">" } else { destination.to_owned() };
+				let (title, attributes) = link_title(title.as_deref());
+				"[".to_owned() + label + "]: " + &destination + &title + if attributes.is_empty() { "" } else { " " } + &attributes
+			},
+		}
+	}
+
+	fn write_list(&mut self, ordered: bool, items: &[Item], depth: usize) -> String {
+		let marker = if ordered { "#".repeat(depth) + ". " } else { "*".repeat(depth) + " " };
+		let mut out: Vec<String> = Vec::new();
+		for item in items.iter() {
+			if item.checkbox {
+				self.untranslated("Kami has no task lists, so the checkbox was kept as text", item.span.clone());
+			}
+			out.push(self.write_item(&marker, &item.content, Some(depth)));
+		}
+		out.join("\n")
+	}
+
+	/// Writes a list item or a footnote: the text of its first paragraph goes after the marker, and its other blocks are indented under it. The lists at the end of a list item are written with deeper markers instead, unless other blocks come after them.
+	fn write_item(&mut self, marker: &str, content: &[Block], depth: Option<usize>) -> String {
+		let (text, rest) = match content.split_first() {
+			Some((Block::Text { level: None, lines }, rest)) => (self.inlines(&lines.join("\n")).replace('\n', "\n  "), rest),
+			_ => (String::new(), content),
+		};
+		let nested = rest.iter().position(|x| matches!(x, Block::List { .. })).filter(|x| depth.is_some() && rest[*x..].iter().all(|x| matches!(x, Block::List { .. }))).unwrap_or(rest.len());
+		let mut out = marker.to_owned() + &text;
+		if nested > 0 {
+			let body = self.write_blocks(&rest[..nested], 1);
+			out += "\n";
+			for line in body.lines() {
+				out += &(if line.is_empty() { "\n".to_owned() } else { "\n  ".to_owned() + line });
+			}
+		}
+		for block in rest[nested..].iter() {
+			out += &("\n".to_owned() + &self.write_block(block, depth.unwrap_or_default() + 1));
+		}
+		out
+	}
+
+	/// Converts the inline Markdown of a block into Kami.
+	fn inlines(&mut self, text: &str) -> String {
+		let mut nodes: Vec<Node> = Vec::new();
+		// Where the [ and ![ that aren't closed yet are
+		let mut brackets: Vec<usize> = Vec::new();
+		let mut text_start = 0;
+		let mut pos = 0;
+		let bytes = text.as_bytes();
+		let push_text = |nodes: &mut Vec<Node>, from: usize, to: usize| {
+			if from < to {
+				nodes.push(Node::Text(text[from..to].to_owned()));
+			}
+		};
+		while pos < text.len() {
+			let cha = text[pos..].chars().next().unwrap_or_default();
+			let before = text[..pos].chars().next_back();
+			let mut next: Option<(Node, usize)> = None;
+			match cha {
+				'\\' => {
+					next = match text[pos + 1..].chars().next() {
+						Some('\n') => Some((Node::Kami("\n".to_owned(), " ".to_owned()), pos + 2)),
+						Some(x) if x.is_ascii_punctuation() => Some((Node::Text(x.to_string()), pos + 2)),
+						_ => None,
+					};
+				},
+				'`' => {
+					let len = run(text, pos, '`');
+					next = Some(match code_span(text, pos, len) {
+						// Kami code can't have a backtick, nor the raw HTML that writes a | in table cells, so code with either is written with HTML tags
+						Some((code, end)) => (Node::Kami(if code.contains(['`', '|']) { "<code>".to_owned() + &escape(&code) + "</code>" } else { "`".to_owned() + &code + "`" }, code), end),
+						None => (Node::Text("`".repeat(len)), pos + len),
+					});
+				},
+				'*' | '_' | '~' => {
+					let len = run(text, pos, cha);
+					let after = text[pos + len..].chars().next();
+					let (left, right) = flanking(before, after);
+					let (open, close) = match cha {
+						'_' => (left && (!right || before.is_some_and(is_punctuation)), right && (!left || after.is_some_and(is_punctuation))),
+						_ => (left, right),
+					};
+					let node = if cha == '~' && len > 2 { Node::Text("~".repeat(len)) } else { Node::Delim { cha, count: len, length: len, open, close } };
+					next = Some((node, pos + len));
+				},
+				'!' if bytes.get(pos + 1) == Some(&b'[') => next = Some((Node::Bracket { image: true, active: true, start: pos + 2 }, pos + 2)),
+				'[' => {
+					let footnote = text[pos..].strip_prefix("[^").and_then(|x| x.split_once(']')).filter(|(label, _)| !label.is_empty() && !label.contains(['[', ' ']) && self.footnotes.contains(&normalize(label)));
+					next = Some(match footnote {
+						Some((label, _)) => (Node::Kami("[^".to_owned() + label + "]", String::new()), pos + label.len() + 3),
+						None => (Node::Bracket { image: false, active: true, start: pos + 1 }, pos + 1),
+					});
+				},
+				']' => {
+					push_text(&mut nodes, text_start, pos);
+					let end = self.close_bracket(text, pos, &mut nodes, &mut brackets);
+					text_start = end;
+					pos = end;
+					continue;
+				},
+				'<' => next = autolink(text, pos).or_else(|| raw_html(text, pos).map(|end| (Node::Kami(text[pos..end].to_owned(), String::new()), end))),
+				'&' => {
+					let end = text[pos..].find(';').map(|x| pos + x + 1).filter(|end| end - pos > 2 && end - pos < 34 && text[pos + 1..end - 1].chars().all(|x| x.is_ascii_alphanumeric() || x == '#'));
+					if let Some(end) = end {
+						let decoded: String = decode(&text[pos..end]).into_iter().collect();
+						if decoded != text[pos..end] {
+							next = Some((Node::Text(decoded), end));
+						}
+					}
+				},
+				'\n' => {
+					// Two spaces at the end of a line make a hard break, anything else is just a space
+					let line = &text[text_start..pos];
+					let hard = line.ends_with("  ");
+					push_text(&mut nodes, text_start, text_start + line.trim_end().len());
+					next = Some((if hard { Node::Kami("\n".to_owned(), " ".to_owned()) } else { Node::Text(" ".to_owned()) }, pos + 1));
+				},
+				_ => (),
+			}
+			match next {
+				Some((node, end)) => {
+					if cha != '\n' {
+						push_text(&mut nodes, text_start, pos);
+					}
+					if let Node::Bracket { .. } = node {
+						brackets.push(nodes.len());
+					}
+					nodes.push(node);
+					text_start = end;
+					pos = end;
+				},
+				None => pos += cha.len_utf8(),
+			}
+		}
+		push_text(&mut nodes, text_start, text.len());
+		emphasis(&mut nodes, 0);
+		render(&nodes).0.trim().to_owned()
+	}
+
+	/// Tries to make a link or an image out of the text between the last `[` and the `]` at `pos`. Returns where the text after it starts.
+	fn close_bracket(&mut self, text: &str, pos: usize, nodes: &mut Vec<Node>, brackets: &mut Vec<usize>) -> usize {
+		let Some(opener) = brackets.pop() else {
+			nodes.push(Node::Text("]".to_owned()));
+			return pos + 1
+		};
+		let Node::Bracket { image, active, start } = nodes[opener] else { return pos + 1 };
+		let label_text = &text[start..pos];
+		let after = &text[pos + 1..];
+		let found: Option<(String, Option<String>, Option<String>, usize)> = if !active {
+			None
+		} else if let Some((destination, title, len)) = after.strip_prefix('(').and_then(inline_destination) {
+			Some((destination, title, None, pos + 2 + len))
+		} else {
+			// A full reference like [text][label], a collapsed one like [text][], or a shortcut like [text]
+			let (label, end) = match after.strip_prefix('[').and_then(|x| x.split_once(']')) {
+				Some(("", _)) => (label_text, pos + 3),
+				Some((label, _)) if !label.contains('[') => (label, pos + 3 + label.len()),
+				_ => (label_text, pos + 1),
+			};
+			// Like in CommonMark, labels can't be longer than 999 characters
+			if label.len() > 999 { None } else { self.definitions.iter().find(|x| x.0 == normalize(label)).map(|x| (x.2.clone(), x.3.clone(), Some(x.1.clone()), end)) }
+		};
+		let Some((destination, title, reference, end)) = found else {
+			nodes[opener] = Node::Text(if image { "![" } else { "[" }.to_owned());
+			nodes.push(Node::Text("]".to_owned()));
+			return pos + 1
+		};
+		emphasis(nodes, opener + 1);
+		let (content, plain) = render(&nodes[opener + 1..]);
+		nodes.truncate(opener);
+		let kami = if image {
+			let mut attributes = Attributes { pairs: vec![("alt".to_owned(), Some(plain.clone()))], ..Default::default() };
+			if let Some(title) = &title {
+				attributes.pairs.push(("title".to_owned(), Some(title.to_owned())));
+			}
+			"!".to_owned() + &encode_destination(&destination).replace('!', "%21") + "!" + &attributes.to_string()
+		} else {
+			// Links can't have links inside them
+			for bracket in brackets.iter() {
+				if let Node::Bracket { image: false, active, .. } = &mut nodes[*bracket] {
+					*active = false;
+				}
+			}
+			match reference {
+				Some(label) => "[".to_owned() + &content + "][" + &label + "]",
+				None => {
+					let (title, attributes) = link_title(title.as_deref());
+					"[".to_owned() + &content + "](" + &encode_destination(&destination) + &title + ")" + &attributes
+				},
+			}
+		};
+		nodes.push(Node::Kami(kami, plain));
+		end
+	}
+}
+
+enum Node {
+	/// Text to be escaped.
+	Text(String),
+	/// Kami that's already converted, with its plain text for alt texts.
+	Kami(String, String),
+	/// A run of `*`, `_` or `~`, and how much of it is left after matching it with other runs.
+	Delim { cha: char, count: usize, length: usize, open: bool, close: bool },
+	Bracket { image: bool, active: bool, start: usize },
+}
+
+/// Matches the runs of `*`, `_` and `~` after `bottom` with each other, like CommonMark does, and turns them into Kami emphasis, strong and strikethrough.
+fn emphasis(nodes: &mut [Node], bottom: usize) {
+	// Where to stop looking for openers for each kind of closer, since the ones below that were already looked at and didn't match
+	let mut floors: Vec<((char, bool, usize), usize)> = Vec::new();
+	let mut iter = bottom;
+	while iter < nodes.len() {
+		let Node::Delim { cha, count, length, close: true, open: closer_opens } = nodes[iter] else {
+			iter += 1;
+			continue
+		};
+		let key = (cha, closer_opens, length % 3);
+		let floor = floors.iter().find(|x| x.0 == key).map_or(bottom, |x| x.1);
+		let opener = (floor..iter).rev().find(|x| match nodes[*x] {
+			Node::Delim { cha: other, count: other_count, length: other_length, open: true, close: opener_closes } => {
+				other == cha && (cha != '~' || other_count == count)
+					// The rule of 3 keeps *a**b* from matching the * with the **
+					&& !((closer_opens || opener_closes) && (length + other_length) % 3 == 0 && (length % 3 != 0 || other_length % 3 != 0))
+			},
+			_ => false,
+		});
+		let Some(opener) = opener else {
+			floors.retain(|x| x.0 != key);
+			floors.push((key, iter));
+			iter += 1;
+			continue
+		};
+		let Node::Delim { count: opener_count, .. } = nodes[opener] else { break };
+		let used = if cha == '~' { count } else if count >= 2 && opener_count >= 2 { 2 } else { 1 };
+		let delimiter = match (cha, used) {
+			('~', _) => "~~",
+			(_, 2) => "**",
+			_ => "__",
+		};
+		let (content, plain) = render(&nodes[opener + 1..iter]);
+		// The nodes that were used up are left empty instead of removed, so that long lines don't take quadratic time
+		nodes[opener + 1] = Node::Kami(delimiter.to_owned() + &content + delimiter, plain);
+		for node in nodes[opener + 2..iter].iter_mut() {
+			*node = Node::Kami(String::new(), String::new());
+		}
+		for index in [opener, iter] {
+			if let Node::Delim { count, .. } = &mut nodes[index] {
+				*count -= used;
+				if *count == 0 {
+					nodes[index] = Node::Kami(String::new(), String::new());
+				}
+			}
+		}
+		if !matches!(nodes[iter], Node::Delim { .. }) {
+			iter += 1;
+		}
+	}
+}
+
+/// Writes nodes as Kami, and as plain text.
+fn render(nodes: &[Node]) -> (String, String) {
+	let mut kami = String::new();
+	let mut plain = String::new();
+	for node in nodes.iter() {
+		let text = match node {
+			Node::Kami(text, text_plain) => {
+				kami += text;
+				plain += text_plain;
+				continue;
+			},
+			Node::Text(text) => text.to_owned(),
+			Node::Delim { cha, count, .. } => cha.to_string().repeat(*count),
+			Node::Bracket { image: true, .. } => "![".to_owned(),
+			Node::Bracket { image: false, .. } => "[".to_owned(),
+		};
+		kami += &escape(&text);
+		plain += &text;
+	}
+	(kami, plain)
+}
+
+/// Escapes every character that could start a Kami token.
+fn escape(text: &str) -> String {
+	let mut out = String::new();
+	let chars: Vec<char> = text.chars().collect();
+	for (iter, cha) in chars.iter().enumerate() {
+		// A single - is just a dash, but -- underlines
+		let dash = *cha == '-' && (chars.get(iter + 1) == Some(&'-') || (iter > 0 && chars[iter - 1] == '-'));
+		if dash || matches!(cha, '\\' | '*' | '_' | '~' | '^' | '@' | '`' | '[' | ']' | '!' | '{' | '}' | '<') {
+			out.push('\\');
+		}
+		out.push(*cha);
+	}
+	out
+}
+
+/// Escapes the text between the tags of a line of an HTML block, which Kami would parse.
+fn html_line(line: &str) -> String {
+	let mut out = String::new();
+	let mut rest = line;
+	while let Some(start) = rest.find('<') {
+		let end = raw_html(rest, start).unwrap_or(start + 1);
+		out += &escape(&decode(&rest[..start]).into_iter().collect::<String>());
+		out += if end > start + 1 { &rest[start..end] } else { "\\<" };
+		rest = &rest[end..];
+	}
+	out += &escape(&decode(rest).into_iter().collect::<String>());
+	// Lines that don't start with a tag need <> to not be paragraphs
+	if out.starts_with('<') || out.is_empty() { out } else { "<> ".to_owned() + &out }
+}
+
+fn encode_destination(destination: &str) -> String {
+	destination.replace(' ', "%20").replace('(', "%28").replace(')', "%29")
+}
+
+/// Writes a link title after the destination, between whichever quotes it doesn't have. Titles with both go in an attribute sequence instead, which is the second string.
+fn link_title(title: Option<&str>) -> (String, String) {
+	match title {
+		Some(title) if !title.contains('"') => (" \"".to_owned() + title + "\"", String::new()),
+		Some(title) if !title.contains('\'') => (" '".to_owned() + title + "'", String::new()),
+		Some(title) => (String::new(), Attributes { pairs: vec![("title".to_owned(), Some(title.to_owned()))], ..Default::default() }.to_string()),
+		None => (String::new(), String::new()),
+	}
+}
+
+/// Labels match regardless of case and spacing.
+fn normalize(label: &str) -> String {
+	label.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
+
+/// Removes backslash escapes.
+fn unescape(text: &str) -> String {
+	let mut out = String::new();
+	let mut chars = text.chars().peekable();
+	while let Some(cha) = chars.next() {
+		match chars.peek() {
+			Some(next) if cha == '\\' && next.is_ascii_punctuation() => {
+				out.push(*next);
+				chars.next();
+			},
+			_ => out.push(cha),
+		}
+	}
+	out
+}
+
+fn is_punctuation(cha: char) -> bool {
+	cha.is_ascii_punctuation() || (!cha.is_alphanumeric() && !cha.is_whitespace() && !cha.is_control())
+}
+
+/// Whether a run of delimiters can open and close emphasis, from the characters around it.
+fn flanking(before: Option<char>, after: Option<char>) -> (bool, bool) {
+	let space = |x: Option<char>| x.is_none_or(char::is_whitespace);
+	let punctuation = |x: Option<char>| x.is_some_and(is_punctuation);
+	let left = !space(after) && (!punctuation(after) || space(before) || punctuation(before));
+	let right = !space(before) && (!punctuation(before) || space(after) || punctuation(after));
+	(left, right)
+}
+
+fn run(text: &str, pos: usize, cha: char) -> usize {
+	text[pos..].len() - text[pos..].trim_start_matches(cha).len()
+}
+
+/// Finds the end of the code span whose opening backticks are at `pos`, and returns its content.
+fn code_span(text: &str, pos: usize, len: usize) -> Option<(String, usize)> {
+	let mut search = pos + len;
+	while let Some(found) = text[search..].find('`') {
+		let start = search + found;
+		let closing = run(text, start, '`');
+		if closing == len {
+			let code = text[pos + len..start].replace('\n', " ");
+			let code = match code.strip_prefix(' ').and_then(|x| x.strip_suffix(' ')) {
+				Some(inner) if !code.trim().is_empty() => inner.to_owned(),
+				_ => code,
+			};
+			return Some((code, start + len))
+		}
+		search = start + closing;
+	}
+	None
+}
+
+/// Reads a `<https://example.com>` or `<me@example.com>` autolink.
+fn autolink(text: &str, pos: usize) -> Option<(Node, usize)> {
+	let end = pos + text[pos..].find('>')?;
+	let inner = &text[pos + 1..end];
+	if inner.contains(|x: char| x.is_whitespace() || x == '<') {
+		return None
+	}
+	let scheme = inner.split_once(':').map(|x| x.0).filter(|x| (2..=32).contains(&x.len()) && x.starts_with(|x: char| x.is_ascii_alphabetic()) && x.chars().all(|x| x.is_ascii_alphanumeric() || matches!(x, '+' | '.' | '-')));
+	let destination = if scheme.is_some() {
+		inner.to_owned()
+	} else if inner.split_once('@').is_some_and(|(user, domain)| !user.is_empty() && domain.contains('.') && !domain.contains('@')) {
+		"mailto:".to_owned() + inner
+	} else {
+		return None
+	};
+	Some((Node::Kami("[".to_owned() + &escape(inner) + "](" + &encode_destination(&destination) + ")", inner.to_owned()), end + 1))
+}
+
+/// Finds the end of the HTML tag or comment at `pos`.
+fn raw_html(text: &str, pos: usize) -> Option<usize> {
+	let rest = &text[pos..];
+	if rest.starts_with("<!--") {
+		return rest.find("-->").map(|x| pos + x + 3)
+	}
+	let name = rest.strip_prefix("</").or_else(|| rest.strip_prefix('<'))?;
+	if !name.starts_with(|x: char| x.is_ascii_alphabetic()) {
+		return None
+	}
+	// Skip over quoted attribute values, which can have a > in them
+	let mut quote: Option<char> = None;
+	for (iter, cha) in rest.char_indices().skip(1) {
+		match (quote, cha) {
+			(Some(x), _) if x == cha => quote = None,
+			(Some(_), _) => (),
+			(None, '"' | '\'') => quote = Some(cha),
+			(None, '<') => return None,
+			(None, '>') => return Some(pos + iter + 1),
+			_ => (),
+		}
+	}
+	None
+}
+
+/// Reads the `(destination "title")` of an inline link, after its `(`. Returns the destination, the title and how long it was, counting the `)`.
+fn inline_destination(text: &str) -> Option<(String, Option<String>, usize)> {
+	let trimmed = text.trim_start();
+	let mut pos = text.len() - trimmed.len();
+	let destination = if let Some(rest) = trimmed.strip_prefix('<') {
+		let end = rest.find(['>', '\n'])?;
+		if !rest[end..].starts_with('>') {
+			return None
+		}
+		pos += end + 2;
+		rest[..end].to_owned()
+	} else {
+		// Bare destinations end at a space or at the ) that balances their parentheses
+		let mut depth = 0;
+		let mut end = trimmed.len();
+		let mut escaped = false;
+		for (iter, cha) in trimmed.char_indices() {
+			match cha {
+				_ if escaped => escaped = false,
+				'\\' => escaped = true,
+				// Like most implementations, only 32 levels of parentheses are read, so that long lines of them don't take forever
+				'(' if depth == 32 => return None,
+				'(' => depth += 1,
+				')' if depth == 0 => {
+					end = iter;
+					break;
+				},
+				')' => depth -= 1,
+				x if x.is_whitespace() => {
+					end = iter;
+					break;
+				},
+				_ => (),
+			}
+		}
+		pos += end;
+		trimmed[..end].to_owned()
+	};
+	let rest = &text[pos..];
+	let trimmed = rest.trim_start();
+	pos += rest.len() - trimmed.len();
+	let mut title = None;
+	if let Some(close) = trimmed.chars().next().and_then(|x| match x { '"' => Some('"'), '\'' => Some('\''), '(' => Some(')'), _ => None }) {
+		let end = trimmed[1..].find(close)? + 1;
+		title = Some(unescape(&trimmed[1..end]));
+		pos += end + 1;
+		let rest = &text[pos..];
+		pos += rest.len() - rest.trim_start().len();
+	}
+	if !text[pos..].starts_with(')') {
+		return None
+	}
+	Some((unescape(&destination), title, pos + 1))
+}
+
+/// Reads a link reference definition like `[label]: https://example.com "Title"`, which has to fit in one line.
+fn link_definition(line: &str) -> Option<(&str, String, Option<String>)> {
+	let (label, rest) = line.strip_prefix('[')?.split_once("]:")?;
+	if label.trim().is_empty() || label.starts_with('^') || label.contains('[') {
+		return None
+	}
+	let rest = rest.trim();
+	let (destination, title) = match rest.strip_prefix('<') {
+		Some(inner) => {
+			let (destination, title) = inner.split_once('>')?;
+			(destination, title.trim())
+		},
+		None => rest.split_once(char::is_whitespace).map(|(x, y)| (x, y.trim())).unwrap_or((rest, "")),
+	};
+	if destination.is_empty() && !rest.starts_with('<') {
+		return None
+	}
+	let title = match title.chars().next() {
+		None => None,
+		Some(open) => {
+			let close = match open { '"' => '"', '\'' => '\'', '(' => ')', _ => return None };
+			Some(unescape(title.strip_prefix(open)?.strip_suffix(close)?))
+		},
+	};
+	Some((label, unescape(destination), title))
+}
+
+fn footnote_definition(line: &str) -> Option<(&str, &str)> {
+	let (label, rest) = line.strip_prefix("[^")?.split_once("]:")?;
+	if label.is_empty() || label.contains(char::is_whitespace) {
+		return None
+	}
+	Some((label, rest.trim_start()))
+}
+
+/// Whether a line would keep going a paragraph if the next line lazily continues it.
+fn is_lazy(line: &str) -> bool {
+	!line.trim().is_empty() && fence(line.trim_start()).is_none() && indentation(line) < 4
+}
+
+/// Whether a line starts a block that can interrupt a paragraph.
+fn starts_block(line: &str) -> bool {
+	let rest = line.trim_start();
+	indentation(line) < 4 && (rest.starts_with('>') || fence(rest).is_some() || atx_header(rest).is_some() || is_rule(rest)
+		|| list_marker(line).is_some_and(|x| !x.empty && (!x.ordered || x.start == 1))
+		// HTML blocks that start with any other tag can't interrupt paragraphs
+		|| (rest.starts_with('<') && (rest.starts_with("<!--") || BLOCK_TAGS.contains(&tag_name(rest).as_str()) || RAW_TAGS.contains(&tag_name(rest).as_str()))))
+}
+
+fn indentation(line: &str) -> usize {
+	let mut width = 0;
+	for cha in line.chars() {
+		match cha {
+			' ' => width += 1,
+			'\t' => width += 4 - width % 4,
+			_ => break,
+		}
+	}
+	width
+}
+
+/// Removes up to `width` columns of indentation.
+fn strip_indent(line: &str, width: usize) -> &str {
+	let mut columns = 0;
+	for (iter, cha) in line.char_indices() {
+		if columns >= width {
+			return &line[iter..]
+		}
+		match cha {
+			' ' => columns += 1,
+			'\t' => columns += 4 - columns % 4,
+			_ => return &line[iter..],
+		}
+	}
+	""
+}
+
+fn fence(rest: &str) -> Option<(char, usize)> {
+	let cha = rest.chars().next().filter(|x| *x == '`' || *x == '~')?;
+	let len = run(rest, 0, cha);
+	// The info string of backtick fences can't have backticks
+	(len >= 3 && !(cha == '`' && rest[len..].contains('`'))).then_some((cha, len))
+}
+
+fn atx_header(rest: &str) -> Option<usize> {
+	let level = run(rest, 0, '#');
+	((1..=6).contains(&level) && rest[level..].chars().next().is_none_or(char::is_whitespace)).then_some(level)
+}
+
+fn is_rule(rest: &str) -> bool {
+	let cha = rest.chars().next().unwrap_or_default();
+	matches!(cha, '*' | '-' | '_') && rest.chars().filter(|x| !x.is_whitespace()).all(|x| x == cha) && rest.matches(cha).count() >= 3
+}
+
+fn list_marker(line: &str) -> Option<Marker> {
+	let indent = indentation(line);
+	if indent >= 4 {
+		return None
+	}
+	let rest = line.trim_start();
+	if is_rule(rest) {
+		return None
+	}
+	let digits = rest.len() - rest.trim_start_matches(|x: char| x.is_ascii_digit()).len();
+	let (ordered, delimiter, start, len) = match rest.chars().next()? {
+		x @ ('-' | '*' | '+') => (false, x, 1, 1),
+		_ if (1..=9).contains(&digits) => {
+			let delimiter = rest[digits..].chars().next().filter(|x| *x == '.' || *x == ')')?;
+			(true, delimiter, rest[..digits].parse().ok()?, digits + 1)
+		},
+		_ => return None,
+	};
+	let after = &rest[len..];
+	if !after.is_empty() && !after.starts_with([' ', '\t']) {
+		return None
+	}
+	let spaces = indentation(after);
+	let empty = after.trim().is_empty();
+	// Content indented by more than 4 spaces is indented code, with the 1 space after the marker left out
+	let (width, content) = if empty || spaces > 4 {
+		(indent + len + 1, line.len() - after.len() + after.len().min(1))
+	} else {
+		(indent + len + spaces, line.len() - after.trim_start().len())
+	};
+	Some(Marker { ordered, delimiter, start, width, content, empty })
+}
+
+/// Finds the end of the HTML block starting at `start`, if one starts there.
+fn html_block(lines: &[Line], start: usize) -> Option<usize> {
+	let rest = lines[start].1.trim_start();
+	if indentation(lines[start].1) >= 4 || !rest.starts_with('<') {
+		return None
+	}
+	let name = tag_name(rest);
+	let closing = if rest.starts_with("<!--") {
+		Some("-->".to_owned())
+	} else if RAW_TAGS.contains(&name.as_str()) && !rest.starts_with("</") {
+		Some(format!("</{}>", name))
+	} else if BLOCK_TAGS.contains(&name.as_str()) || raw_html(rest, 0).is_some_and(|end| rest[end..].trim().is_empty()) {
+		None
+	} else {
+		return None
+	};
+	let mut end = start;
+	while end < lines.len() {
+		let text = lines[end].1;
+		end += 1;
+		match &closing {
+			Some(closing) if text.to_lowercase().contains(closing.as_str()) => break,
+			Some(_) => (),
+			None if lines.get(end).is_none_or(|x| x.1.trim().is_empty()) => break,
+			None => (),
+		}
+	}
+	Some(end)
+}
+
+fn tag_name(tag: &str) -> String {
+	tag.trim_start_matches(['<', '/']).chars().take_while(|x| x.is_ascii_alphanumeric()).collect::<String>().to_lowercase()
+}
+
+/// Finds the end of the table starting at `start`, if its next line is a delimiter row with as many cells as it.
+fn table(lines: &[Line], start: usize) -> Option<usize> {
+	let header = lines[start].1;
+	let delimiter = lines.get(start + 1)?.1;
+	if !header.contains('|') || indentation(header) >= 4 {
+		return None
+	}
+	let cells = split_row(delimiter);
+	if cells.is_empty() || cells.len() != split_row(header).len() || !cells.iter().all(|x| { let x = x.trim_matches(':'); !x.is_empty() && x.chars().all(|x| x == '-') }) {
+		return None
+	}
+	let mut end = start + 2;
+	while end < lines.len() && !lines[end].1.trim().is_empty() && !starts_block(lines[end].1) {
+		end += 1;
+	}
+	Some(end)
+}
+
+/// Splits a table row into its cells, on the pipes that aren't escaped.
+fn split_row(line: &str) -> Vec<String> {
+	let line = line.trim();
+	let line = line.strip_prefix('|').unwrap_or(line);
+	let line = if line.ends_with('|') && !line.ends_with("\\|") { &line[..line.len() - 1] } else { line };
+	let mut cells = vec![String::new()];
+	let mut chars = line.chars().peekable();
+	while let Some(cha) = chars.next() {
+		match cha {
+			'\\' if chars.peek() == Some(&'|') => {
+				cells.last_mut().unwrap_or(&mut String::new()).push('|');
+				chars.next();
+			},
+			'|' => cells.push(String::new()),
+			_ => cells.last_mut().unwrap_or(&mut String::new()).push(cha),
+		}
+	}
+	cells.into_iter().map(|x| x.trim().to_owned()).collect()
+}
+
+fn column_alignment(spec: &str) -> Option<Alignment> {
+	let spec = spec.trim();
+	match (spec.starts_with(':'), spec.ends_with(':')) {
+		(true, true) => Some(Alignment::Center),
+		(true, false) => Some(Alignment::Left),
+		(false, true) => Some(Alignment::Right),
+		(false, false) => None,
+	}
+}
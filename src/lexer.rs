@@ -1,3 +1,8 @@
+use crate::attributes::Attributes;
+use crate::autolink::link_len;
+use crate::diagnostic::Diagnostic;
+use std::ops::Range;
+
 #[derive(Clone, Debug)]
 pub(crate) enum TokenType {
 	Put,
@@ -29,6 +34,20 @@ pub(crate) enum TokenType {
 	TableCell,
 	TableHeader,
 	Table,
+	CodeBlock,
+	Quote,
+	Citation,
+	ItemBody,
+	DefList,
+	DefTerm,
+	DefDesc,
+	FootnoteDef,
+	Toc,
+	LinkDef,
+	Figure,
+	Caption,
+	TableColumns,
+	TableFoot,
 }
 
 #[derive(Clone, Debug)]
@@ -37,41 +56,101 @@ pub(crate) struct Token {
 	pub(crate) content: String,
 	pub(crate) subtokens: Vec<Token>,
 	pub(crate) attributes: String,
+	/// Byte range of the token in the source.
+	pub(crate) span: Range<usize>,
 }
 
 impl Token {
 	pub(crate) fn new() -> Self {
-		Self { class: TokenType::Put, content: String::new(), subtokens: Vec::new(), attributes: String::new() }
+		Self { class: TokenType::Put, content: String::new(), subtokens: Vec::new(), attributes: String::new(), span: 0..0 }
 	}
 	pub(crate) fn n_para() -> Self {
-		Self { class: TokenType::Para, content: String::new(), subtokens: Vec::new(), attributes: String::new() }
+		Self { class: TokenType::Para, content: String::new(), subtokens: Vec::new(), attributes: String::new(), span: 0..0 }
 	}
 	pub(crate) fn init(class: TokenType, content: String) -> Self {
-		Self { class: class, content: content, subtokens: Vec::new(), attributes: String::new() }
+		Self { class, content, subtokens: Vec::new(), attributes: String::new(), span: 0..0 }
 	}
 	pub(crate) fn init_sub(class: TokenType, tcontent: Vec<Self>, content: String) -> Self {
-		Self { class: class, content: content, subtokens: tcontent, attributes: String::new() }
+		Self { class, content, subtokens: tcontent, attributes: String::new(), span: 0..0 }
+	}
+	pub(crate) fn at(mut self, pos: usize) -> Self {
+		self.span = pos..pos;
+		self
 	}
-	pub(crate) fn tokenize_content(self: &mut Self, borders: usize) {
-		self.subtokens = tokenize(&self.content[borders..self.content.len()-borders]).0;
+	/// Tokenizes the content of the token between its delimiters, which are `borders` characters long. Returns the warnings about that content.
+	#[must_use]
+	pub(crate) fn tokenize_content(&mut self, borders: usize) -> Vec<Diagnostic> {
+		let (subtokens, warnings) = tokenize(&self.content[borders..self.content.len()-borders], self.span.start + borders);
+		self.subtokens = subtokens;
+		warnings
 	}
-	pub(crate) fn tokenize_unclosed(self: &mut Self, borders: usize) {
-		self.subtokens = tokenize(&self.content[borders..self.content.len()]).0;
+}
+
+/// The label of a footnote reference like `[^label]`, from the content of its token.
+pub(crate) fn footnote_label(content: &str) -> Option<&str> {
+	content.strip_prefix("[^").and_then(|x| x.strip_suffix(']')).filter(|x| !x.is_empty() && !x.contains(char::is_whitespace))
+}
+
+pub(crate) fn tokenize(input: &str, offset: usize) -> (Vec<Token>, Vec<Diagnostic>) {
+	let (mut tokens, mut warnings, mut rest) = tokenize_part(input, offset);
+	// The text after an unclosed token is tokenized again in a loop rather than recursively, so a long line of unclosed tokens can't overflow the stack
+	while let Some((input, offset)) = rest {
+		let (mut more_tokens, mut more_warnings, more_rest) = tokenize_part(&input, offset);
+		tokens.append(&mut more_tokens);
+		warnings.append(&mut more_warnings);
+		rest = more_rest;
+	}
+	close_spans(&mut tokens, offset + input.len());
+	(tokens, warnings)
+}
+
+/// Whether a word can start at `pos`, after whitespace or an opening parenthesis or quote.
+fn starts_word(input: &str, pos: usize) -> bool {
+	input[..pos].chars().next_back().is_none_or(|x| x.is_whitespace() || matches!(x, '(' | '"' | '\''))
+}
+
+/// Tokens are only given their start while tokenizing, each one ends where the next one starts.
+fn close_spans(tokens: &mut [Token], end: usize) {
+	let mut end = end;
+	for token in tokens.iter_mut().rev() {
+		token.span.end = end.max(token.span.start);
+		end = token.span.start;
 	}
 }
 
-pub(crate) fn tokenize(input: &str) -> (Vec<Token>, String) {
+/// Tokenizes `input` until the end or until an unclosed token is found. In the latter case, it also returns the text that still needs tokenizing.
+fn tokenize_part(input: &str, offset: usize) -> (Vec<Token>, Vec<Diagnostic>, Option<(String, usize)>) {
 	let mut tokens:Vec<Token> = vec![];
-	let mut current_token: Token = Token::new();
+	let mut rest = None;
+	let mut current_token: Token = Token::new().at(offset);
 	let mut escaping = false;
 
-	let mut warnings = String::new();
+	let mut warnings: Vec<Diagnostic> = Vec::new();
 
 	let mut nlist_wait_space = false;
 
 	let mut strong_wait = false; // Variable used for closing a STRONG token
+	let mut attr_quote: Option<char> = None; // The quote of the attribute value being read, a } inside of it doesn't close the sequence
+	let mut skip_to = 0; // The end of a URL or email address that was added to the text all at once
 	for (pos, cha) in input.char_indices() {
-		if cha == '\\'{
+		if pos < skip_to { continue; }
+		let here = offset + pos;
+		let next = here + cha.len_utf8();
+		// URLs and email addresses are kept whole, so an `_` or `@` inside of them doesn't start a token
+		let link = match current_token.class {
+			TokenType::Put if !escaping && starts_word(input, pos) => link_len(&input[pos..]),
+			_ => None,
+		};
+		if let Some(len) = link {
+			current_token.content += &input[pos..pos + len];
+			skip_to = pos + len;
+			continue;
+		}
+		if cha == '\\' && matches!(current_token.class, TokenType::Attr) {
+			// Attribute sequences keep their backslashes, the attribute parser handles them
+			current_token.content.push(cha);
+			escaping = !escaping;
+		} else if cha == '\\'{
 			if escaping {
 				escaping = false;
 				current_token.content += &cha.to_string();
@@ -89,71 +168,71 @@ pub(crate) fn tokenize(input: &str) -> (Vec<Token>, String) {
 						'*' => {
 							if !escaping {
 								push_token(&mut tokens, &current_token);
-								current_token = Token::init(TokenType::Bold, cha.to_string());
+								current_token = Token::init(TokenType::Bold, cha.to_string()).at(here);
 							} else { current_token.content += &cha.to_string(); }
 						},
 						'_' => {
 							if !escaping {
 								push_token(&mut tokens, &current_token);
-								current_token = Token::init(TokenType::Italic, cha.to_string());
+								current_token = Token::init(TokenType::Italic, cha.to_string()).at(here);
 							} else { current_token.content += &cha.to_string(); }
 						},
 						'=' => {
 							if escaping {
 								push_token(&mut tokens, &current_token);
-								current_token = Token::init(TokenType::Raw, String::new());
+								current_token = Token::init(TokenType::Raw, String::new()).at(here - 1);
 							} else { current_token.content += &cha.to_string(); }
 						},
 						'[' => {
 							if !escaping {
 								push_token(&mut tokens, &current_token);
-								current_token = Token::init(TokenType::LinkName, cha.to_string());
+								current_token = Token::init(TokenType::LinkName, cha.to_string()).at(here);
 							} else { current_token.content += &cha.to_string(); }
 						},
 						'~' => {
 							if !escaping {
 								push_token(&mut tokens, &current_token);
-								current_token = Token::init(TokenType::Sub, cha.to_string());
+								current_token = Token::init(TokenType::Sub, cha.to_string()).at(here);
 							} else { current_token.content += &cha.to_string(); }
 						},
 						'^' => {
 							if !escaping {
 								push_token(&mut tokens, &current_token);
-								current_token = Token::init(TokenType::Sup, cha.to_string());
+								current_token = Token::init(TokenType::Sup, cha.to_string()).at(here);
 							} else { current_token.content += &cha.to_string(); }
 						},
 						'!' => {
 							if !escaping {
 								push_token(&mut tokens, &current_token);
-								current_token = Token::init(TokenType::Image, cha.to_string());
+								current_token = Token::init(TokenType::Image, cha.to_string()).at(here);
 							} else { current_token.content += &cha.to_string(); }
 						},
 						'`' => {
 							if !escaping {
 								push_token(&mut tokens, &current_token);
-								current_token = Token::init(TokenType::Code, cha.to_string());
+								current_token = Token::init(TokenType::Code, cha.to_string()).at(here);
 							} else { current_token.content += &cha.to_string(); }
 						},
 						'@' => {
 							if !escaping {
 								push_token(&mut tokens, &current_token);
-								current_token = Token::init(TokenType::Span, cha.to_string());
+								current_token = Token::init(TokenType::Span, cha.to_string()).at(here);
 							} else { current_token.content += &cha.to_string(); }
 						},
 						'-' => {
 							if !escaping {
 								push_token(&mut tokens, &current_token);
-								current_token = Token::init(TokenType::Under, cha.to_string());
+								current_token = Token::init(TokenType::Under, cha.to_string()).at(here);
 							} else { current_token.content += &cha.to_string(); }
 						},
 						'#' => {
-							if pos == 0 { current_token = Token::init(TokenType::Header, cha.to_string()); }
+							if pos == 0 { current_token = Token::init(TokenType::Header, cha.to_string()).at(here); }
 							else { current_token.content += &cha.to_string(); }
 						},
 						'<' => {
 							if !escaping {
 								push_token(&mut tokens, &current_token);
-								current_token = Token::init(TokenType::Html, cha.to_string());
+								current_token = Token::init(TokenType::Html, cha.to_string()).at(here);
 							} else { current_token.content += &cha.to_string(); }
 						},
 						'(' => {
@@ -162,7 +241,7 @@ pub(crate) fn tokenize(input: &str) -> (Vec<Token>, String) {
 									None => current_token.content += &cha.to_string(),
 									Some(last_token) => {
 										match last_token.class {
-											TokenType::LinkName => current_token = Token::init(TokenType::LinkDir, cha.to_string()),
+											TokenType::LinkName => current_token = Token::init(TokenType::LinkDir, cha.to_string()).at(here),
 											_ => current_token.content += &cha.to_string(),
 										}
 									}
@@ -173,14 +252,14 @@ pub(crate) fn tokenize(input: &str) -> (Vec<Token>, String) {
 							if !escaping {
 								push_token(&mut tokens, &current_token);
 								match tokens.last() {
-									None => current_token = Token::init(TokenType::Attr, cha.to_string()),
+									None => current_token = Token::init(TokenType::Attr, cha.to_string()).at(here),
 									Some(last_token) => {
 										match last_token.class {
 											TokenType::Put => {
 												tokens.pop();
 												current_token.content += &cha.to_string();
 											},
-											_ => current_token = Token::init(TokenType::Attr, cha.to_string()),
+											_ => current_token = Token::init(TokenType::Attr, cha.to_string()).at(here),
 										}
 									}
 								}
@@ -190,16 +269,16 @@ pub(crate) fn tokenize(input: &str) -> (Vec<Token>, String) {
 							if !escaping {
 								if tokens.is_empty() && current_token.content.is_empty() {
 									push_token(&mut tokens, &current_token);
-									current_token = Token::init(TokenType::TableRow, String::new());
+									current_token = Token::init(TokenType::TableRow, String::new()).at(here);
 								} else { current_token.content += &cha.to_string() }
 							} else { current_token.content += &cha.to_string() }
 						}
 						'n' => {
 							if escaping {
 								push_token(&mut tokens, &current_token);
-								current_token = Token::init(TokenType::LineBreak, String::from("BR"));
+								current_token = Token::init(TokenType::LineBreak, String::from("BR")).at(here - 1);
 								push_token(&mut tokens, &current_token);
-								current_token = Token::new();
+								current_token = Token::new().at(next);
 							} else {
 								current_token.content += &cha.to_string();
 							}
@@ -213,7 +292,7 @@ pub(crate) fn tokenize(input: &str) -> (Vec<Token>, String) {
 						'=' => {
 							if !escaping {
 								push_token(&mut tokens, &current_token);
-								current_token = Token::new();
+								current_token = Token::new().at(next);
 							}
 						},
 						_ => current_token.content += &cha.to_string()
@@ -225,19 +304,17 @@ pub(crate) fn tokenize(input: &str) -> (Vec<Token>, String) {
 						'*' => {
 							if current_token.content == "**" && !escaping { current_token.class = TokenType::Strong; }
 							else if !escaping {
-								current_token.tokenize_content(1);
+								warnings.append(&mut current_token.tokenize_content(1));
 								push_token(&mut tokens, &current_token);
-								current_token = Token::new();
+								current_token = Token::new().at(next);
 							}
 						},
-						' ' => {
-							if current_token.content == "* " && !escaping {
-								if pos != 1 { current_token.class = TokenType::Put;	}
-								else {
-									current_token.class = TokenType::ListEl;
-									push_token(&mut tokens, &current_token);
-									current_token = Token::new();
-								}
+						' ' if current_token.content == "* " && !escaping => {
+							if pos != 1 { current_token.class = TokenType::Put;	}
+							else {
+								current_token.class = TokenType::ListEl;
+								push_token(&mut tokens, &current_token);
+								current_token = Token::new().at(next);
 							}
 						},
 						_ => (),
@@ -245,28 +322,28 @@ pub(crate) fn tokenize(input: &str) -> (Vec<Token>, String) {
 				},
 				TokenType::Strong => {
 					current_token.content += &cha.to_string();
+					// Both closing characters have to be next to each other
+					if cha != '*' { strong_wait = false; }
 					match cha {
 						'*' => {
 							if !(pos == 2 && current_token.content == "***") {
 								if !escaping && !strong_wait { strong_wait = true; }
 								else if !escaping && strong_wait {
-									current_token.tokenize_content(2);
+									warnings.append(&mut current_token.tokenize_content(2));
 									push_token(&mut tokens, &current_token);
-									current_token = Token::new();
+									current_token = Token::new().at(next);
 									strong_wait = false;
 								} else { strong_wait = false; }
 							} else {
 								current_token.class = TokenType::ListEl;
 							}
 						},
-						' ' => {
-							if current_token.content == "** " && !escaping {
-								if pos != 2 { current_token.class = TokenType::Put; }
-								else {
-									current_token.class = TokenType::ListEl;
-									push_token(&mut tokens, &current_token);
-									current_token = Token::new();
-								}
+						' ' if current_token.content == "** " && !escaping => {
+							if pos != 2 { current_token.class = TokenType::Put; }
+							else {
+								current_token.class = TokenType::ListEl;
+								push_token(&mut tokens, &current_token);
+								current_token = Token::new().at(next);
 							}
 						},
 						_ => (),
@@ -278,7 +355,7 @@ pub(crate) fn tokenize(input: &str) -> (Vec<Token>, String) {
 						'*' => (),
 						' ' => {
 							push_token(&mut tokens, &current_token);
-							current_token = Token::new();
+							current_token = Token::new().at(next);
 						},
 						_ => current_token.class = TokenType::Put,
 					}
@@ -289,126 +366,113 @@ pub(crate) fn tokenize(input: &str) -> (Vec<Token>, String) {
 						'_' => {
 							if current_token.content == "__" && !escaping { current_token.class = TokenType::Emphasis; }
 							else if !escaping {
-								current_token.tokenize_content(1);
+								warnings.append(&mut current_token.tokenize_content(1));
 								push_token(&mut tokens, &current_token);
-								current_token = Token::new();
+								current_token = Token::new().at(next);
 							}
 						},
-						' ' => if current_token.content == "_ " && !escaping { current_token.class = TokenType::Put },
+						' ' if current_token.content == "_ " && !escaping => current_token.class = TokenType::Put,
 						_ => (),
 					}
 				},
 				TokenType::Emphasis => {
 					current_token.content += &cha.to_string();
+					// Both closing characters have to be next to each other
+					if cha != '_' { strong_wait = false; }
 					match cha {
 						'_' => {
 							if !escaping && !strong_wait { strong_wait = true; }
 							else if !escaping && strong_wait {
-								current_token.tokenize_content(2);
+								warnings.append(&mut current_token.tokenize_content(2));
 								push_token(&mut tokens, &current_token);
-								current_token = Token::new();
+								current_token = Token::new().at(next);
 								strong_wait = false;
 							} else { strong_wait = false; }
 						},
-						' ' => if current_token.content == "__ " && !escaping { current_token.class = TokenType::Put },
+						' ' if current_token.content == "__ " && !escaping => current_token.class = TokenType::Put,
 						_ => (),
 					}
 				},
 				TokenType::Sub => {
 					current_token.content += &cha.to_string();
 					match cha {
-						'~' => {
-							if !escaping {
-								if current_token.content == "~~" {
-									current_token.class = TokenType::Strike;
-								} else {
-									current_token.tokenize_content(1);
-									push_token(&mut tokens, &current_token);
-									current_token = Token::new();
-								}
-							} 
+						'~' if !escaping => {
+							if current_token.content == "~~" {
+								current_token.class = TokenType::Strike;
+							} else {
+								warnings.append(&mut current_token.tokenize_content(1));
+								push_token(&mut tokens, &current_token);
+								current_token = Token::new().at(next);
+							}
 						},
-						' ' => if current_token.content == "~ " && !escaping { current_token.class = TokenType::Put },
+						' ' if current_token.content == "~ " && !escaping => current_token.class = TokenType::Put,
 						_ => (),
 					}
 				},
 				TokenType::Image => {
 					current_token.content += &cha.to_string();
 					match cha {
-						'!' => {
-							if !escaping {
-								if current_token.content == "!!" {
-									current_token.class = TokenType::Put;
-								} else {
-									push_token(&mut tokens, &current_token);
-									current_token = Token::new();
-								}
-							}
-						},
-						' ' => {
-							if !escaping {
-								if current_token.content == "! " {
-									current_token.class = TokenType::Put;
-								}
+						'!' if !escaping => {
+							if current_token.content == "!!" {
+								current_token.class = TokenType::Put;
+							} else {
+								push_token(&mut tokens, &current_token);
+								current_token = Token::new().at(next);
 							}
 						},
+						' ' if !escaping && current_token.content == "! " => current_token.class = TokenType::Put,
 						_ => (),
 					}
 				},
 				TokenType::Sup => {
 					current_token.content += &cha.to_string();
 					match cha {
-						'^' => {
-							if !escaping {
-								current_token.tokenize_content(1);
-								push_token(&mut tokens, &current_token);
-								current_token = Token::new();
-							} 
+						'^' if !escaping => {
+							warnings.append(&mut current_token.tokenize_content(1));
+							push_token(&mut tokens, &current_token);
+							current_token = Token::new().at(next);
 						},
-						' ' => if current_token.content == "^ " && !escaping { current_token.class = TokenType::Put },
+						' ' if current_token.content == "^ " && !escaping => current_token.class = TokenType::Put,
 						_ => (),
 					}
 				},
 				TokenType::Span => {
 					current_token.content += &cha.to_string();
 					match cha {
-						'@' => {
-							if !escaping {
-								current_token.tokenize_content(1);
-								push_token(&mut tokens, &current_token);
-								current_token = Token::new();
-							} 
+						'@' if !escaping => {
+							warnings.append(&mut current_token.tokenize_content(1));
+							push_token(&mut tokens, &current_token);
+							current_token = Token::new().at(next);
 						},
-						' ' => if current_token.content == "@ " && !escaping { current_token.class = TokenType::Put },
+						' ' if current_token.content == "@ " && !escaping => current_token.class = TokenType::Put,
 						_ => (),
 					}
 				},
 				TokenType::Code => {
 					current_token.content += &cha.to_string();
 					match cha {
-						'`' => {
-							if !escaping {
-								current_token.tokenize_content(1);
-								push_token(&mut tokens, &current_token);
-								current_token = Token::new();
-							} 
+						'`' if !escaping => {
+							push_token(&mut tokens, &current_token);
+							current_token = Token::new().at(next);
 						},
 						_ => (),
 					}
 				},
 				TokenType::Strike => {
 					current_token.content += &cha.to_string();
+					// Both closing characters have to be next to each other
+					if cha != '~' { strong_wait = false; }
 					match cha {
 						'~' => {
 							if !escaping && !strong_wait { strong_wait = true; }
 							else if !escaping && strong_wait {
-								current_token.tokenize_content(2);
+								warnings.append(&mut current_token.tokenize_content(2));
 								push_token(&mut tokens, &current_token);
-								current_token = Token::new();
+								current_token = Token::new().at(next);
 								strong_wait = false;
 							} else { strong_wait = false; }
 						},
-						' ' => if current_token.content == "~~ " && !escaping { current_token.class = TokenType::Put },
+						' ' if current_token.content == "~~ " && !escaping => current_token.class = TokenType::Put,
 						_ => (),
 					}
 				},
@@ -422,17 +486,18 @@ pub(crate) fn tokenize(input: &str) -> (Vec<Token>, String) {
 					}
 					else {
 						current_token.content += &cha.to_string();
+						if cha != '-' { strong_wait = false; }
 						match cha {
 							'-' => {
 								if !escaping && !strong_wait { strong_wait = true; }
 								else if !escaping && strong_wait {
-									current_token.tokenize_content(2);
+									warnings.append(&mut current_token.tokenize_content(2));
 									push_token(&mut tokens, &current_token);
-									current_token = Token::new();
+									current_token = Token::new().at(next);
 									strong_wait = false;
 								} else { strong_wait = false; }
 							},
-							' ' => if current_token.content == "-- " && !escaping { current_token.class = TokenType::Put },
+							' ' if current_token.content == "-- " && !escaping => current_token.class = TokenType::Put,
 							_ => (),
 						}
 					}
@@ -440,26 +505,25 @@ pub(crate) fn tokenize(input: &str) -> (Vec<Token>, String) {
 				TokenType::Html => {
 					current_token.content += &cha.to_string();
 					match cha {
-						'>' => {
-							if !escaping {
-								current_token.tokenize_content(1);
-								push_token(&mut tokens, &current_token);
-								current_token = Token::new();
-							}
+						'>' if !escaping => {
+							push_token(&mut tokens, &current_token);
+							current_token = Token::new().at(next);
 						},
-						' ' => if current_token.content == "< " && !escaping { current_token.class = TokenType::Put },
+						' ' if current_token.content == "< " && !escaping => current_token.class = TokenType::Put,
 						_ => (),
 					}
 				},
 				TokenType::LinkName => {
 					current_token.content += &cha.to_string();
 					match cha {
-						']' => {
-							if !escaping {
-								current_token.tokenize_content(1);
-								push_token(&mut tokens, &current_token);
-								current_token = Token::new();
+						']' if !escaping => {
+							let mut warns = current_token.tokenize_content(1);
+							// The label of a footnote reference isn't parsed, so whatever is in it isn't unclosed
+							if footnote_label(&current_token.content).is_none() {
+								warnings.append(&mut warns);
 							}
+							push_token(&mut tokens, &current_token);
+							current_token = Token::new().at(next);
 						},
 						_ => (),
 					}
@@ -467,31 +531,36 @@ pub(crate) fn tokenize(input: &str) -> (Vec<Token>, String) {
 				TokenType::LinkDir => {
 					current_token.content += &cha.to_string();
 					match cha {
-						')' => {
-							if !escaping {
-								push_token(&mut tokens, &current_token);
-								current_token = Token::new();
-							}
+						')' if !escaping => {
+							push_token(&mut tokens, &current_token);
+							current_token = Token::new().at(next);
 						},
 						_ => (),
 					}
 				},
 				TokenType::Attr => {
+					let after_equals = current_token.content.ends_with('=');
 					current_token.content += &cha.to_string();
 					match cha {
-						'}' => {
-							if !escaping {
-								match tokens.last_mut() {
-									None => {
-										push_token(&mut tokens, &current_token);
-									},
-									Some(last_token) =>	{
-										last_token.attributes = current_token.content.clone();
-									},
-								}
-								current_token = Token::new();
+						'"' | '\'' if !escaping => {
+							match attr_quote {
+								Some(quote) if quote == cha => attr_quote = None,
+								None if after_equals => attr_quote = Some(cha),
+								_ => (),
 							}
 						},
+						'}' if !escaping && attr_quote.is_none() => {
+							warnings.extend(Attributes::parse(&current_token.content, current_token.span.start).1);
+							match tokens.last_mut() {
+								None => {
+									push_token(&mut tokens, &current_token);
+								},
+								Some(last_token) =>	{
+									last_token.attributes = current_token.content.clone();
+								},
+							}
+							current_token = Token::new().at(next);
+						},
 						_ => (),
 					}
 				},
@@ -504,17 +573,17 @@ pub(crate) fn tokenize(input: &str) -> (Vec<Token>, String) {
 						'{' => {
 							nlist_wait_space = false;
 							push_token(&mut tokens, &current_token);
-							current_token = Token::init(TokenType::Attr, cha.to_string());
+							current_token = Token::init(TokenType::Attr, cha.to_string()).at(here);
 						},
 						' ' => {
 							if !nlist_wait_space {
 								push_token(&mut tokens, &current_token);
-								current_token = Token::new();
+								current_token = Token::new().at(next);
 							} else {
 								current_token.content += " ";
 								current_token.class = TokenType::NumberedListEl;
 								push_token(&mut tokens, &current_token);
-								current_token = Token::new();
+								current_token = Token::new().at(next);
 							}
 							nlist_wait_space = false;
 						},
@@ -522,7 +591,7 @@ pub(crate) fn tokenize(input: &str) -> (Vec<Token>, String) {
 							if !nlist_wait_space { nlist_wait_space = true; }
 							else { 
 								push_token(&mut tokens, &current_token);
-								current_token = Token::new();
+								current_token = Token::new().at(here);
 								current_token.content += &cha.to_string();
 								nlist_wait_space = false;
 							}
@@ -530,12 +599,16 @@ pub(crate) fn tokenize(input: &str) -> (Vec<Token>, String) {
 						_ => {
 							nlist_wait_space = false;
 							push_token(&mut tokens, &current_token);
-							current_token = Token::new();
+							current_token = Token::new().at(here);
 							current_token.content += &cha.to_string();
 						}
 					}
 				},
-				_ => panic!("Reached undefined token type {:?}", current_token.class),
+				_ => {
+					warnings.push(Diagnostic::error("unexpected-token", format!("Reached undefined token type {:?}, so it was outputted as text", current_token.class), current_token.span.start..next));
+					current_token.class = TokenType::Put;
+					current_token.content += &cha.to_string();
+				},
 			}
 		}
 		if escaping && cha != '\\' { escaping = false; }
@@ -543,41 +616,37 @@ pub(crate) fn tokenize(input: &str) -> (Vec<Token>, String) {
 	if !current_token.content.is_empty() {
 		match current_token.class {
 			TokenType::Put | TokenType::TableRow => (),
-			_ => warnings += &format!("WARNING: Unclosed {:?} token at {}\n", current_token.class, current_token.content),
+			_ => warnings.push(Diagnostic::warning("unclosed-token", format!("Unclosed {:?} token at {}", current_token.class, current_token.content), current_token.span.start..offset + input.len())),
 		}
 		match current_token.class {
 			TokenType::Bold | TokenType::Italic | TokenType::Sub | TokenType::Sup | TokenType::LinkName | TokenType::LinkDir | TokenType::Attr | TokenType::Image | TokenType::Html | TokenType::Code | TokenType::Span => {
-				push_token(&mut tokens, &Token::init(TokenType::Put, current_token.content[0..1].to_string()));
-				current_token.tokenize_unclosed(1);
-				tokens.append(&mut current_token.subtokens);
+				push_token(&mut tokens, &Token::init(TokenType::Put, current_token.content[0..1].to_string()).at(current_token.span.start));
+				rest = Some((current_token.content[1..].to_owned(), current_token.span.start + 1));
 			},
 			TokenType::Strong | TokenType::Emphasis | TokenType::Strike => {
-				push_token(&mut tokens, &Token::init(TokenType::Put, current_token.content[0..2].to_string()));
-				current_token.tokenize_unclosed(2);
-				tokens.append(&mut current_token.subtokens);
+				push_token(&mut tokens, &Token::init(TokenType::Put, current_token.content[0..2].to_string()).at(current_token.span.start));
+				rest = Some((current_token.content[2..].to_owned(), current_token.span.start + 2));
 			},
 			TokenType::Under => {
 				if current_token.content == "-" {
-					push_token(&mut tokens, &Token::init(TokenType::Put, current_token.content));
-					tokens.append(&mut current_token.subtokens);
+					push_token(&mut tokens, &Token::init(TokenType::Put, current_token.content).at(current_token.span.start));
 				} else {
-					push_token(&mut tokens, &Token::init(TokenType::Put, current_token.content[0..2].to_string()));
-					current_token.tokenize_unclosed(2);
-					tokens.append(&mut current_token.subtokens);
+					push_token(&mut tokens, &Token::init(TokenType::Put, current_token.content[0..2].to_string()).at(current_token.span.start));
+					rest = Some((current_token.content[2..].to_owned(), current_token.span.start + 2));
 				}
 			},
 			TokenType::Put | TokenType::TableRow => push_token(&mut tokens, &current_token),
 			_ => { 
 				push_token(&mut tokens, &current_token);
-				warnings += "The unclosing of the last token was impossible to handle for Kami, so the raw text has been outputted. Please contact the project maintainer about this.\n";
+				warnings.push(Diagnostic::warning("unhandled-unclosed-token", "The unclosing of the last token was impossible to handle for Kami, so the raw text has been outputted. Please contact the project maintainer about this.".to_owned(), current_token.span.start..offset + input.len()));
 			}
 		}
 	}
-	(tokens, warnings)
+	(tokens, warnings, rest)
 }
 
 pub(crate) fn push_token(list: &mut Vec<Token>, token: &Token) {
-	if token.content != "" || !token.subtokens.is_empty() { list.push(token.clone()); }
+	if !token.content.is_empty() || !token.subtokens.is_empty() { list.push(token.clone()); }
 }
 
 
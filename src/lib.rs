@@ -1,3 +1,17 @@
+pub mod ast;
+pub mod attributes;
+mod autolink;
+pub mod diagnostic;
+pub mod format;
+pub mod html;
+pub mod images;
+pub mod import;
+pub mod markdown;
+pub mod render;
+pub mod sanitize;
 pub mod syntax;
+mod tables;
+pub mod text;
+pub mod toc;
 pub mod lexer;
 pub mod multiline_lexer;
@@ -1,12 +1,263 @@
-mod syntax;
-mod multiline_lexer;
-mod lexer;
+use kami_parser::diagnostic::Diagnostic;
+use kami_parser::{html, images, import, markdown, syntax, text};
+use kami_parser::sanitize::SafeMode;
+use kami_parser::syntax::Options;
 
 use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 
-fn main() {
-	let out = syntax::parse(&fs::read_to_string("example.km").unwrap());
-	out.0.lines().map(|x| print!("{}\n", x)).for_each(drop);
-	println!("\n");
-	out.1.lines().map(|x| println!("{:?}", x)).for_each(drop);
+const USAGE: &str = "Usage: kami [OPTIONS] [INPUT]...
+       kami import [OPTIONS] [INPUT]...
+
+Converts Kami documents. INPUT can be a file, a directory (every .km file in it is converted into a mirrored tree under --output) or - for stdin. With no INPUT, stdin is read.
+
+kami import converts Markdown documents (.md files, for directories) into Kami instead, and warns about anything that couldn't be translated. Only --output, --deny-warnings and --quiet apply to it.
+
+Options:
+  -o, --output <PATH>    Write to PATH instead of stdout. Must be a directory when converting directories or several files
+  -f, --format <FORMAT>  Output format: html (default), markdown or text
+      --width <WIDTH>    Wrap lines of text at WIDTH characters (text only)
+      --fragment         Output only the converted document (default)
+      --standalone       Output a full HTML page (HTML only)
+      --title <TITLE>    Title of standalone pages (defaults to the file name)
+      --source-lines     Add a data-line attribute with the source line to block level HTML elements
+      --header-ids       Give headers without an id one made from their text
+      --eager-images     Don't add loading=\"lazy\" to images
+      --check-images     Warn about local images that don't exist next to the document
+      --pad-tables       Add empty cells to table rows that are shorter than the others
+      --safe             Treat the input as untrusted: filter raw HTML and attributes, and drop javascript: URLs
+      --deny-warnings    Exit with a non-zero code if there are any warnings
+  -q, --quiet            Don't print warnings
+  -h, --help             Print this message
+  -V, --version          Print the version";
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+	Html,
+	Markdown,
+	Text,
+}
+
+impl Format {
+	fn extension(&self) -> &'static str {
+		match self {
+			Format::Html => "html",
+			Format::Markdown => "md",
+			Format::Text => "txt",
+		}
+	}
+}
+
+struct Args {
+	inputs: Vec<String>,
+	output: Option<PathBuf>,
+	format: Format,
+	width: Option<usize>,
+	standalone: bool,
+	title: Option<String>,
+	deny_warnings: bool,
+	quiet: bool,
+	check_images: bool,
+	/// Whether the inputs are Markdown to turn into Kami.
+	import: bool,
+	options: Options,
+}
+
+fn parse_args(args: impl Iterator<Item = String>) -> Result<Option<Args>, String> {
+	let mut out = Args { inputs: Vec::new(), output: None, format: Format::Html, width: None, standalone: false, title: None, deny_warnings: false, quiet: false, check_images: false, import: false, options: Options::default() };
+	let mut args = args.peekable();
+	if args.next_if(|x| x == "import").is_some() {
+		out.import = true;
+	}
+	let mut only_inputs = false;
+	while let Some(arg) = args.next() {
+		if only_inputs || arg == "-" || !arg.starts_with('-') {
+			out.inputs.push(arg);
+			continue;
+		}
+		let mut value = |name: &str| args.next().ok_or(format!("{} needs a value", name));
+		match arg.as_str() {
+			"-o" | "--output" => out.output = Some(PathBuf::from(value(&arg)?)),
+			"-f" | "--format" => {
+				out.format = match value(&arg)?.as_str() {
+					"html" => Format::Html,
+					"markdown" | "md" => Format::Markdown,
+					"text" | "txt" => Format::Text,
+					x => return Err(format!("Unknown format {}", x)),
+				}
+			},
+			"--width" => {
+				let width = value(&arg)?;
+				out.width = Some(width.parse().map_err(|_| format!("Invalid width {}", width))?);
+			},
+			"--fragment" => out.standalone = false,
+			"--standalone" => out.standalone = true,
+			"--title" => out.title = Some(value(&arg)?),
+			"--source-lines" => out.options.source_lines = true,
+			"--header-ids" => out.options.header_ids = true,
+			"--eager-images" => out.options.lazy_images = false,
+			"--check-images" => out.check_images = true,
+			"--pad-tables" => out.options.pad_tables = true,
+			"--safe" => out.options.safe_mode = Some(SafeMode::default()),
+			"--deny-warnings" => out.deny_warnings = true,
+			"-q" | "--quiet" => out.quiet = true,
+			"--" => only_inputs = true,
+			"-h" | "--help" => {
+				println!("{}", USAGE);
+				return Ok(None)
+			},
+			"-V" | "--version" => {
+				println!("kami {}", env!("CARGO_PKG_VERSION"));
+				return Ok(None)
+			},
+			_ => return Err(format!("Unknown option {}", arg)),
+		}
+	}
+	if out.inputs.is_empty() {
+		out.inputs.push("-".to_owned());
+	}
+	Ok(Some(out))
+}
+
+/// Converts a single document, printing its warnings. Returns the output and whether there were any warnings.
+fn convert(source: &str, name: &str, args: &Args) -> (String, bool) {
+	if args.import {
+		let (out, warnings) = import::markdown(source);
+		print_warnings(name, &warnings, args);
+		return (out, !warnings.is_empty())
+	}
+	let mut options = args.options.clone();
+	if args.check_images {
+		options.image_resolver = Some(images::local_files(Path::new(name).parent().unwrap_or(Path::new(""))));
+	}
+	let (document, mut warnings) = syntax::parse_document_with(source, &options);
+	let out = match args.format {
+		Format::Html => {
+			let body = html::render_with(&document, &options);
+			if args.standalone {
+				let title = match &args.title {
+					Some(title) => title.to_owned(),
+					None => Path::new(name).file_stem().map(|x| x.to_string_lossy().into_owned()).unwrap_or_default(),
+				};
+				html::standalone(&title, &body)
+			} else {
+				body
+			}
+		},
+		Format::Markdown => {
+			let (out, mut lossy) = markdown::render_with(&document, &options);
+			for warning in lossy.iter_mut() {
+				warning.locate(source);
+			}
+			warnings.extend(lossy);
+			out
+		},
+		Format::Text => text::render_with(&document, &options, args.width),
+	};
+	print_warnings(name, &warnings, args);
+	(out, !warnings.is_empty())
+}
+
+fn print_warnings(name: &str, warnings: &[Diagnostic], args: &Args) {
+	if args.quiet {
+		return
+	}
+	for warning in warnings.iter() {
+		eprintln!("{}: {}", name, warning);
+	}
+}
+
+fn read_input(input: &str) -> Result<String, String> {
+	if input == "-" {
+		let mut out = String::new();
+		io::stdin().read_to_string(&mut out).map_err(|x| format!("Couldn't read stdin: {}", x))?;
+		Ok(out)
+	} else {
+		fs::read_to_string(input).map_err(|x| format!("Couldn't read {}: {}", input, x))
+	}
+}
+
+fn write_output(path: Option<&Path>, content: &str) -> Result<(), String> {
+	match path {
+		None => io::stdout().write_all(content.as_bytes()).map_err(|x| format!("Couldn't write to stdout: {}", x)),
+		Some(path) => {
+			if let Some(parent) = path.parent() {
+				fs::create_dir_all(parent).map_err(|x| format!("Couldn't create {}: {}", parent.display(), x))?;
+			}
+			fs::write(path, content).map_err(|x| format!("Couldn't write {}: {}", path.display(), x))
+		},
+	}
+}
+
+/// Finds every file with one of `extensions` under `dir`, sorted so output is deterministic.
+fn find_documents(dir: &Path, extensions: &[&str], out: &mut Vec<PathBuf>) -> Result<(), String> {
+	let entries = fs::read_dir(dir).map_err(|x| format!("Couldn't read {}: {}", dir.display(), x))?;
+	let mut paths: Vec<PathBuf> = entries.filter_map(|x| x.ok()).map(|x| x.path()).collect();
+	paths.sort();
+	for path in paths {
+		if path.is_dir() {
+			find_documents(&path, extensions, out)?;
+		} else if path.extension().is_some_and(|x| extensions.iter().any(|y| x == *y)) {
+			out.push(path);
+		}
+	}
+	Ok(())
+}
+
+/// Converts everything, returning whether there were any warnings.
+fn run(args: &Args) -> Result<bool, String> {
+	let mut warned = false;
+	let batch = args.inputs.len() > 1 || args.inputs.iter().any(|x| Path::new(x).is_dir());
+	if !batch {
+		let input = &args.inputs[0];
+		let name = if input == "-" { "<stdin>" } else { input };
+		let (out, warns) = convert(&read_input(input)?, name, args);
+		write_output(args.output.as_deref(), &out)?;
+		return Ok(warns)
+	}
+	for input in args.inputs.iter() {
+		let root = Path::new(input);
+		let documents = if root.is_dir() {
+			let mut documents = Vec::new();
+			find_documents(root, if args.import { &["md", "markdown"] } else { &["km"] }, &mut documents)?;
+			documents
+		} else {
+			vec![root.to_path_buf()]
+		};
+		for document in documents {
+			let name = document.to_string_lossy().into_owned();
+			let (out, warns) = convert(&read_input(&name)?, &name, args);
+			warned |= warns;
+			match &args.output {
+				None => write_output(None, &out)?,
+				Some(dir) => {
+					let relative = if root.is_dir() { document.strip_prefix(root).unwrap_or(&document) } else { Path::new(document.file_name().unwrap_or_default()) };
+					let extension = if args.import { "km" } else { args.format.extension() };
+					write_output(Some(&dir.join(relative).with_extension(extension)), &out)?;
+				},
+			}
+		}
+	}
+	Ok(warned)
+}
+
+fn main() -> ExitCode {
+	let args = match parse_args(std::env::args().skip(1)) {
+		Ok(Some(args)) => args,
+		Ok(None) => return ExitCode::SUCCESS,
+		Err(x) => {
+			eprintln!("kami: {}\n\n{}", x, USAGE);
+			return ExitCode::from(2)
+		},
+	};
+	match run(&args) {
+		Ok(warned) if warned && args.deny_warnings => ExitCode::from(1),
+		Ok(_) => ExitCode::SUCCESS,
+		Err(x) => {
+			eprintln!("kami: {}", x);
+			ExitCode::from(2)
+		},
+	}
 }
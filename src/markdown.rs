@@ -0,0 +1,441 @@
+use crate::ast::{Alignment, Attributes, Block, BlockKind, DefinitionEntry, Document, Footnote, Image, Inline, InlineKind, ListItem, TableColumn, TableRow, TocEntry};
+use crate::diagnostic::Diagnostic;
+use crate::html::{self, HtmlRenderer};
+use crate::render::Renderer;
+use crate::syntax::Options;
+use std::ops::Range;
+
+pub fn render(document: &Document) -> (String, Vec<Diagnostic>) {
+	render_with(document, &Options::default())
+}
+
+/// Renders a document as CommonMark, with the GitHub extensions for tables, strikethrough and footnotes. Also returns a warning for every part of the document that couldn't be written in Markdown. Their `line` is set, but their `column` is only filled in by `Diagnostic::locate`.
+pub fn render_with(document: &Document, options: &Options) -> (String, Vec<Diagnostic>) {
+	let mut renderer = MarkdownRenderer::new(options);
+	let out = renderer.document(document);
+	(out, renderer.warnings)
+}
+
+/// Renders documents as Markdown. What Markdown can't do, like subscripts or cells that span several columns, is written as HTML, which Markdown lets through. What HTML can't help with either, like the attributes of a paragraph, is left out and reported.
+pub struct MarkdownRenderer<'a> {
+	options: &'a Options,
+	warnings: Vec<Diagnostic>,
+}
+
+impl<'a> MarkdownRenderer<'a> {
+	pub fn new(options: &'a Options) -> Self {
+		Self { options, warnings: Vec::new() }
+	}
+
+	/// What couldn't be written in Markdown, in the order it was rendered.
+	pub fn warnings(&self) -> &[Diagnostic] {
+		&self.warnings
+	}
+
+	fn lossy(&mut self, message: String, span: &Range<usize>, line: usize) {
+		let mut warning = Diagnostic::warning("lossy-conversion", message, span.clone());
+		warning.line = line;
+		self.warnings.push(warning);
+	}
+
+	fn drop_attributes(&mut self, attributes: &Attributes, what: &str, span: &Range<usize>, line: usize) {
+		if !attributes.is_empty() {
+			self.lossy(format!("Markdown has no attributes for {}, so {} was left out", what, attributes), span, line);
+		}
+	}
+
+	/// Renders a block as HTML, for the ones Markdown has nothing like.
+	fn html_fallback(&self, block: &Block) -> String {
+		HtmlRenderer::new(self.options).block(block)
+	}
+
+	fn allows_url(&self, url: &str, image: bool) -> bool {
+		self.options.safe_mode.as_ref().is_none_or(|x| x.allows_url(url, image))
+	}
+
+	/// Renders a list item after its marker. The lines after the first one are indented to line up with its text, so that they stay in the item.
+	fn item(&mut self, marker: &str, item: &ListItem) -> String {
+		self.drop_attributes(&item.attributes, "list items", &item.span, item.line);
+		let mut out = marker.to_owned() + &escape_line_starts(&self.inlines(&item.content));
+		match item.children.first() {
+			None => out += "\n",
+			// Lists nested right under the item's text can't have a blank line before them, or the item becomes a paragraph
+			Some(Block { kind: BlockKind::List { .. }, .. }) => out += &("\n".to_owned() + &self.blocks(&item.children)),
+			Some(_) => out += &("\n\n".to_owned() + &self.blocks(&item.children)),
+		}
+		indent(&out, marker.len())
+	}
+
+	/// Renders a row of a GFM table, with every cell on a single line.
+	fn row(&mut self, row: &TableRow, width: usize) -> String {
+		self.drop_attributes(&row.attributes, "table rows", &row.span, row.line);
+		let mut out = "|".to_owned();
+		for cell in row.cells.iter() {
+			self.drop_attributes(&cell.attributes, "table cells", &cell.span, cell.line);
+			let content = self.inlines(&cell.content).replace("\\\n", "<br>").replace('\n', " ");
+			out += &(" ".to_owned() + content.trim() + " |");
+		}
+		for _ in row.cells.len()..width {
+			out += "  |";
+		}
+		out + "\n"
+	}
+}
+
+impl Renderer for MarkdownRenderer<'_> {
+	fn options(&self) -> &Options {
+		self.options
+	}
+
+	fn document(&mut self, document: &Document) -> String {
+		let blocks = self.blocks(&document.blocks);
+		let notes = self.footnotes(&document.footnotes);
+		if notes.is_empty() { blocks } else { blocks + "\n" + &notes }
+	}
+
+	/// Separates the blocks with blank lines.
+	fn blocks(&mut self, blocks: &[Block]) -> String {
+		let mut out: Vec<String> = Vec::new();
+		for (iter, block) in blocks.iter().enumerate() {
+			// Two lists in a row would be read as a single one
+			if iter > 0 && matches!(block.kind, BlockKind::List { .. }) && matches!(blocks[iter - 1].kind, BlockKind::List { .. }) {
+				out.push("<!-- -->\n".to_owned());
+			}
+			out.push(self.block(block));
+		}
+		out.join("\n")
+	}
+
+	fn footnotes(&mut self, notes: &[Footnote]) -> String {
+		let mut out: Vec<String> = Vec::new();
+		for note in notes.iter() {
+			self.drop_attributes(&note.attributes, "footnotes", &note.span, note.line);
+			let mut text = format!("[^{}]: ", note.number) + &self.inlines(&note.content) + "\n";
+			if !note.children.is_empty() {
+				text += &("\n".to_owned() + &self.blocks(&note.children));
+			}
+			out.push(indent(&text, 4));
+		}
+		out.join("\n")
+	}
+
+	fn paragraph(&mut self, block: &Block, content: &[Inline]) -> String {
+		self.drop_attributes(&block.attributes, "paragraphs", &block.span, block.line);
+		escape_line_starts(&self.inlines(content)) + "\n"
+	}
+
+	fn header(&mut self, block: &Block, level: usize, content: &[Inline]) -> String {
+		self.drop_attributes(&block.attributes, "headers", &block.span, block.line);
+		if level > 6 {
+			self.lossy(format!("Markdown headers only go up to level 6, so this level {} header was written as a level 6 one", level), &block.span, block.line);
+		}
+		"#".repeat(level.clamp(1, 6)) + " " + &self.inlines(content).replace('\n', " ") + "\n"
+	}
+
+	fn image_block(&mut self, block: &Block, image: &Image) -> String {
+		if needs_html(image, &block.attributes) {
+			return html::image(self, image, &block.attributes, Some(block.line)) + "\n"
+		}
+		markdown_image(image, &block.attributes, self.allows_url(&image.source, true)) + "\n"
+	}
+
+	fn html_block(&mut self, block: &Block, _tag: &str, _content: &[Inline]) -> String {
+		self.html_fallback(block)
+	}
+
+	fn code_block(&mut self, block: &Block, language: Option<&str>, content: &str) -> String {
+		self.drop_attributes(&block.attributes, "code blocks", &block.span, block.line);
+		let fence = "`".repeat(longest_run(content, '`').max(2) + 1);
+		let mut out = fence.clone() + language.unwrap_or_default() + "\n" + content;
+		if !content.is_empty() && !content.ends_with('\n') {
+			out += "\n";
+		}
+		out + &fence + "\n"
+	}
+
+	fn quote(&mut self, block: &Block, content: &[Block], attribution: Option<&[Inline]>) -> String {
+		self.drop_attributes(&block.attributes, "blockquotes", &block.span, block.line);
+		let mut inner = self.blocks(content);
+		if let Some(attribution) = attribution {
+			inner += &("\n— ".to_owned() + &self.inlines(attribution) + "\n");
+		}
+		let mut out = String::new();
+		for line in inner.lines() {
+			out += &(if line.is_empty() { ">".to_owned() } else { "> ".to_owned() + line } + "\n");
+		}
+		out
+	}
+
+	/// Writes a GFM table if it fits one, or HTML if it has a caption, a foot, several header rows, or cells with spans or blocks.
+	fn table(&mut self, block: &Block, caption: Option<&[Inline]>, columns: &[TableColumn], head: &[TableRow], body: &[TableRow], foot: &[TableRow]) -> String {
+		let cells = || head.iter().chain(body.iter()).flat_map(|x| x.cells.iter());
+		let simple = caption.is_none() && foot.is_empty() && head.len() <= 1 && columns.iter().all(|x| x.attributes.is_empty())
+			&& cells().all(|x| x.children.is_empty() && !x.attributes.has("colspan") && !x.attributes.has("rowspan"));
+		if !simple {
+			return self.html_fallback(block)
+		}
+		self.drop_attributes(&block.attributes, "tables", &block.span, block.line);
+		let rows: Vec<&TableRow> = head.iter().chain(body.iter()).collect();
+		let Some((first, rest)) = rows.split_first() else { return String::new() };
+		if head.is_empty() {
+			self.lossy("Markdown tables need a header row, so the first row of the table became one".to_owned(), &first.span, first.line);
+		}
+		if let Some(cell) = rest.iter().flat_map(|x| x.cells.iter()).find(|x| x.header) {
+			self.lossy("Markdown tables can only have header cells in their first row, so the ones after it became normal cells".to_owned(), &cell.span, cell.line);
+		}
+		let width = rows.iter().map(|x| x.cells.len()).max().unwrap_or(0).max(columns.len()).max(1);
+		let mut out = self.row(first, width) + "|";
+		for column in 0..width {
+			out += match columns.get(column).and_then(|x| x.align) {
+				Some(Alignment::Left) => " :--- |",
+				Some(Alignment::Center) => " :---: |",
+				Some(Alignment::Right) => " ---: |",
+				None => " --- |",
+			};
+		}
+		out += "\n";
+		for row in rest.iter() {
+			out += &self.row(row, width);
+		}
+		out
+	}
+
+	fn figure(&mut self, block: &Block, number: usize, content: &Block, caption: &[Inline]) -> String {
+		self.lossy("Markdown has no figures, so the caption was written as a paragraph after it".to_owned(), &block.span, block.line);
+		let label = if caption.is_empty() { format!("Figure {}", number) } else { format!("Figure {}: ", number) };
+		self.block(content) + "\n" + &label + &self.inlines(caption) + "\n"
+	}
+
+	fn definition_list(&mut self, block: &Block, _entries: &[DefinitionEntry]) -> String {
+		self.html_fallback(block)
+	}
+
+	fn toc(&mut self, block: &Block, entries: &[TocEntry]) -> String {
+		self.drop_attributes(&block.attributes, "tables of contents", &block.span, block.line);
+		toc_entries(entries, 0)
+	}
+
+	fn list(&mut self, block: &Block, ordered: bool, items: &[ListItem]) -> String {
+		self.drop_attributes(&block.attributes, "lists", &block.span, block.line);
+		let mut out = String::new();
+		for (iter, item) in items.iter().enumerate() {
+			let marker = if ordered { format!("{}. ", iter + 1) } else { "- ".to_owned() };
+			out += &self.item(&marker, item);
+		}
+		out
+	}
+
+	fn inlines(&mut self, inlines: &[Inline]) -> String {
+		let mut out = String::new();
+		let mut after_break = false;
+		for inline in inlines.iter() {
+			let text = self.inline(inline);
+			// A ! before a link would turn it into an image
+			if text.starts_with('[') && out.ends_with('!') {
+				out.insert(out.len() - 1, '\\');
+			}
+			// The next line starts right after a line break, so the spaces that came after it in the source don't indent it
+			out += if after_break { text.trim_start() } else { &text };
+			after_break = matches!(inline.kind, InlineKind::LineBreak);
+		}
+		out
+	}
+
+	fn text(&mut self, _inline: &Inline, text: &str) -> String {
+		escape(text)
+	}
+
+	fn raw(&mut self, _inline: &Inline, text: &str) -> String {
+		html::safe_html(text, false, self.options)
+	}
+
+	fn html(&mut self, _inline: &Inline, text: &str) -> String {
+		html::safe_html(text, true, self.options)
+	}
+
+	fn code(&mut self, inline: &Inline, text: &str) -> String {
+		if !inline.attributes.is_empty() {
+			return html::code(self, inline, text)
+		}
+		let fence = "`".repeat(longest_run(text, '`') + 1);
+		// Code that starts or ends with a backtick needs a space to keep it apart from the fence
+		if text.starts_with('`') || text.ends_with('`') {
+			fence.clone() + " " + text + " " + &fence
+		} else {
+			fence.clone() + text + &fence
+		}
+	}
+
+	fn line_break(&mut self, _inline: &Inline) -> String {
+		"\\\n".to_owned()
+	}
+
+	fn image(&mut self, inline: &Inline, image: &Image) -> String {
+		if needs_html(image, &inline.attributes) {
+			return html::image(self, image, &inline.attributes, None)
+		}
+		markdown_image(image, &inline.attributes, self.allows_url(&image.source, true))
+	}
+
+	fn link(&mut self, inline: &Inline, destination: Option<&str>, title: Option<&str>, content: &[Inline]) -> String {
+		if !inline.attributes.is_empty() {
+			return html::link(self, inline, destination, title, content)
+		}
+		let name = self.inlines(content);
+		let Some(destination) = destination else {
+			let url = crate::ast::plain_text(content);
+			return if self.allows_url(&url, false) { "<".to_owned() + &html::escape_url(&url) + ">" } else { name }
+		};
+		if !self.allows_url(destination, false) {
+			return name
+		}
+		"[".to_owned() + &name + "](" + &markdown_url(destination) + &markdown_title(title) + ")"
+	}
+
+	fn link_ref(&mut self, _inline: &Inline, label: &str, content: &[Inline]) -> String {
+		"\\[".to_owned() + &self.inlines(content) + "\\]\\[" + &escape(label) + "\\]"
+	}
+
+	fn footnote_ref(&mut self, _inline: &Inline, label: &str, number: Option<usize>, _reference: usize) -> String {
+		match number {
+			Some(number) => format!("[^{}]", number),
+			None => escape(&("[^".to_owned() + label + "]")),
+		}
+	}
+
+	fn bold(&mut self, inline: &Inline, content: &[Inline]) -> String {
+		styled(self, "**", "b", inline, content)
+	}
+
+	fn strong(&mut self, inline: &Inline, content: &[Inline]) -> String {
+		styled(self, "**", "strong", inline, content)
+	}
+
+	fn italic(&mut self, inline: &Inline, content: &[Inline]) -> String {
+		styled(self, "*", "i", inline, content)
+	}
+
+	fn emphasis(&mut self, inline: &Inline, content: &[Inline]) -> String {
+		styled(self, "*", "em", inline, content)
+	}
+
+	fn sub(&mut self, inline: &Inline, content: &[Inline]) -> String {
+		html::wrap(self, "sub", inline, content)
+	}
+
+	fn sup(&mut self, inline: &Inline, content: &[Inline]) -> String {
+		html::wrap(self, "sup", inline, content)
+	}
+
+	fn span(&mut self, inline: &Inline, content: &[Inline]) -> String {
+		if inline.attributes.is_empty() {
+			return self.inlines(content)
+		}
+		html::wrap(self, "span", inline, content)
+	}
+
+	fn strike(&mut self, inline: &Inline, content: &[Inline]) -> String {
+		styled(self, "~~", "del", inline, content)
+	}
+
+	fn under(&mut self, inline: &Inline, content: &[Inline]) -> String {
+		html::wrap(self, "u", inline, content)
+	}
+}
+
+/// Wraps inline content in a Markdown delimiter like `**`, or in an HTML tag if it has attributes.
+fn styled(r: &mut MarkdownRenderer, delimiter: &str, tag: &str, inline: &Inline, content: &[Inline]) -> String {
+	if !inline.attributes.is_empty() {
+		return html::wrap(r, tag, inline, content)
+	}
+	delimiter.to_owned() + &r.inlines(content) + delimiter
+}
+
+/// Images with a size, a srcset or attributes other than their alt text and title are written as HTML.
+fn needs_html(image: &Image, attributes: &Attributes) -> bool {
+	image.width.is_some() || image.height.is_some() || !image.srcset.is_empty() || attributes.id.is_some() || !attributes.classes.is_empty()
+		|| attributes.pairs.iter().any(|(key, _)| key != "alt" && key != "title")
+}
+
+fn markdown_image(image: &Image, attributes: &Attributes, allowed: bool) -> String {
+	let alt = escape(attributes.get("alt").unwrap_or_default());
+	if !allowed {
+		return alt
+	}
+	"![".to_owned() + &alt + "](" + &markdown_url(&image.source) + &markdown_title(attributes.get("title")) + ")"
+}
+
+fn markdown_url(url: &str) -> String {
+	let url = html::escape_url(url);
+	if url.contains(['(', ')']) { "<".to_owned() + &url + ">" } else { url }
+}
+
+fn markdown_title(title: Option<&str>) -> String {
+	match title {
+		Some(title) => " \"".to_owned() + &title.replace('\\', "\\\\").replace('"', "\\\"") + "\"",
+		None => String::new(),
+	}
+}
+
+fn toc_entries(entries: &[TocEntry], depth: usize) -> String {
+	let mut out = String::new();
+	for entry in entries.iter() {
+		out += &"  ".repeat(depth);
+		out += &match &entry.id {
+			Some(id) => "- [".to_owned() + &escape(&entry.title) + "](#" + &html::escape_url(id) + ")\n",
+			None => "- ".to_owned() + &escape(&entry.title) + "\n",
+		};
+		out += &toc_entries(&entry.children, depth + 1);
+	}
+	out
+}
+
+/// Backslash-escapes the characters that mean something inside a line of Markdown.
+fn escape(text: &str) -> String {
+	let mut out = String::new();
+	for (pos, cha) in text.char_indices() {
+		let entity = cha == '&' && text[pos + 1..].find(';').is_some_and(|end| end > 0 && text[pos + 1..pos + 1 + end].chars().all(|x| x.is_ascii_alphanumeric() || x == '#'));
+		if entity || matches!(cha, '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '>' | '|' | '~') {
+			out.push('\\');
+		}
+		out.push(cha);
+	}
+	out
+}
+
+/// Trims the lines of a paragraph, since indented ones would be read as code, and escapes what would make them start a header, a list or a thematic break.
+fn escape_line_starts(text: &str) -> String {
+	let mut lines: Vec<String> = Vec::new();
+	for line in text.split('\n') {
+		let line = line.trim_start();
+		let digits = line.len() - line.trim_start_matches(|x: char| x.is_ascii_digit()).len();
+		let pos = if line.starts_with(['#', '-', '+', '=']) {
+			Some(0)
+		} else if digits > 0 && line[digits..].starts_with(['.', ')']) {
+			Some(digits)
+		} else {
+			None
+		};
+		lines.push(match pos {
+			Some(pos) => line[..pos].to_owned() + "\\" + &line[pos..],
+			None => line.to_owned(),
+		});
+	}
+	lines.join("\n")
+}
+
+/// Indents every line but the first by `width` spaces, leaving blank lines blank.
+fn indent(text: &str, width: usize) -> String {
+	let mut out = String::new();
+	for (iter, line) in text.split_inclusive('\n').enumerate() {
+		if iter > 0 && !line.trim().is_empty() {
+			out += &" ".repeat(width);
+		}
+		out += line;
+	}
+	out
+}
+
+fn longest_run(text: &str, cha: char) -> usize {
+	text.split(|x| x != cha).map(|x| x.len()).max().unwrap_or(0)
+}
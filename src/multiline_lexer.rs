@@ -1,4 +1,17 @@
+use crate::attributes::Attributes;
+use crate::diagnostic::Diagnostic;
 use crate::lexer::{TokenType, Token, push_token, tokenize};
+use std::iter::Peekable;
+use std::slice::Iter;
+
+/// Blockquotes and list items are lexed recursively, so their nesting is limited to keep deep ones from overflowing the stack.
+const MAX_DEPTH: usize = 32;
+
+fn add_lists(tokens: &mut Vec<Token>, lists: &mut Vec<Token>) {
+	if !lists.is_empty() {
+		tokens.push(Token::init_sub(TokenType::ListBlock, std::mem::take(lists), String::new()));
+	}
+}
 
 fn add_table(tokens: &mut Vec<Token>, table: &mut Token) {
 	if !table.subtokens.is_empty() {
@@ -7,7 +20,7 @@ fn add_table(tokens: &mut Vec<Token>, table: &mut Token) {
 	}
 }
 
-fn table_parse(input: &String) -> Token {
+fn table_parse(row: &Token) -> (Token, Vec<Diagnostic>) {
 	enum CellMode {
 		None,
 		Column,
@@ -22,9 +35,16 @@ fn table_parse(input: &String) -> Token {
 	let mut current_cell_col = String::new();
 	let mut current_cell_row = String::new();
 	let mut rowattr = String::new();
-	for ch in input.chars() {
+	let mut warnings: Vec<Diagnostic> = Vec::new();
+	// The row's content starts after its first |
+	let offset = row.span.start + 1;
+	current_cell.span = row.span.start..row.span.start;
+	let mut content_start = offset;
+	let mut attr_start = offset;
+	for (pos, ch) in row.content.char_indices() {
 		if starting_cell {
 			// If it's writing the cell starter token
+			let mut unexpected = None;
 			match ch {
 				'*' => {
 					match cell_mode {
@@ -49,25 +69,30 @@ fn table_parse(input: &String) -> Token {
 						CellMode::Column => current_cell_col += &ch.to_string(),
 						CellMode::Row => current_cell_row += &ch.to_string(),
 						CellMode::Attr => current_cell.attributes += &ch.to_string(),
-						_ => panic!("Found a digit in an unexpected position in cell token"),
+						_ => unexpected = Some("Found a digit in an unexpected position in cell token"),
 					}
 				},
 				'{' => {
 					cell_mode = CellMode::Attr;
+					attr_start = offset + pos;
 					current_cell.attributes += &ch.to_string();
 				}
 				'}'=> {
 					match cell_mode {
 						CellMode::Attr => {
 							cell_mode = CellMode::None;
+							warnings.extend(Attributes::parse(&(current_cell.attributes.clone() + "}"), attr_start).1);
 						},
-						_ => panic!("{}", "Found a } outside an attribute sequence"),
+						_ => unexpected = Some("Found a } outside an attribute sequence"),
 					}
 				},
 				'|' => {
+					if let CellMode::Attr = cell_mode {
+						warnings.push(Diagnostic::warning("unclosed-table-attr", "Unclosed attribute sequence in a table cell".to_owned(), current_cell.span.start..offset + pos));
+					}
 					nullify = false;
 					starting_cell = true;
-					current_cell = Token::init(TokenType::TableCell, String::new());
+					current_cell = Token::init(TokenType::TableCell, String::new()).at(offset + pos);
 					cell_mode = CellMode::None;
 					current_cell_row = String::new();
 					current_cell_col = String::new();
@@ -78,41 +103,40 @@ fn table_parse(input: &String) -> Token {
 						CellMode::Attr => current_cell.attributes += &ch.to_string(),
 						_ => {
 							starting_cell = false;
-							let mut close_atter = !current_cell.attributes.is_empty();
-							if !current_cell_col.is_empty() || !current_cell_row.is_empty() {
-								if !close_atter {
-									current_cell.attributes += "{";
-									close_atter = true;
-								}
-								if !current_cell_row.is_empty() {
-									current_cell.attributes += &(" rowspan=\"".to_owned() + &current_cell_row + "\"");
-								}
-								if !current_cell_col.is_empty() {
-									current_cell.attributes += &(" colspan=\"".to_owned() + &current_cell_col + "\"");
-								}
-							}
-							if close_atter { current_cell.attributes += "}" }
+							content_start = offset + pos + 1;
+							end_cell_starter(&mut current_cell, &current_cell_col, &current_cell_row);
 						}
 					}
 				},
 				_ => {
 					match cell_mode {
 						CellMode::Attr => current_cell.attributes += &ch.to_string(),
-						_ => panic!("Unexpected character in table cell initiation"),
+						_ => unexpected = Some("Unexpected character in table cell initiation"),
 					}
 				},
 			}
+			if let Some(message) = unexpected {
+				// Treat the character as the start of the cell's content
+				warnings.push(Diagnostic::error("table-cell-starter", message.to_owned() + ", so it was treated as part of the cell's content", offset + pos..offset + pos + ch.len_utf8()));
+				starting_cell = false;
+				content_start = offset + pos;
+				end_cell_starter(&mut current_cell, &current_cell_col, &current_cell_row);
+				current_cell.content += &ch.to_string();
+			}
 		} else {
 			// If it's writing the content of the cell
 			match ch {
 				'|' => {
 					if !nullify {
-						current_cell.subtokens = tokenize(&current_cell.content.trim_end_matches('\t')).0;
+						let (subtokens, mut warns) = tokenize(current_cell.content.trim_end_matches('\t'), content_start);
+						current_cell.subtokens = subtokens;
+						warnings.append(&mut warns);
+						current_cell.span.end = offset + pos + 1;
 						out.push(current_cell.clone());
 					}
 					nullify = false;
 					starting_cell = true;
-					current_cell = Token::init(TokenType::TableCell, String::new());
+					current_cell = Token::init(TokenType::TableCell, String::new()).at(offset + pos);
 					cell_mode = CellMode::None;
 					current_cell_row = String::new();
 					current_cell_col = String::new();
@@ -121,32 +145,251 @@ fn table_parse(input: &String) -> Token {
 			}
 		}
 	}
+	let end = offset + row.content.len();
+	if let CellMode::Attr = cell_mode {
+		warnings.push(Diagnostic::warning("unclosed-table-attr", "Unclosed attribute sequence at the end of a table row".to_owned(), current_cell.span.start..end));
+	}
+	if !starting_cell && !current_cell.content.trim().is_empty() {
+		warnings.push(Diagnostic::warning("unclosed-table-cell", "The last cell of a table row isn't closed with a |, so it was dropped".to_owned(), current_cell.span.start..end));
+	}
 	if !current_cell.attributes.is_empty() && current_cell.content.is_empty() {
 		current_cell.attributes += "}";
 		rowattr = current_cell.attributes;
 	}
 	let mut outok = Token::init_sub(TokenType::TableRow, out, String::new());
+	outok.span = row.span.clone();
 	outok.attributes = rowattr;
-	outok
+	(outok, warnings)
+}
+
+/// Reads a column specification row like `|:---|:-:{.price}|---:|`, where each cell has at least three `-` and `:`. A `:` on the left, right or both sides aligns the column that way, and the attributes are for the column's `<col>` element. Each column is kept as a subtoken, with its specification as content.
+fn column_spec(row: &Token) -> Option<(Token, Vec<Diagnostic>)> {
+	let body = row.content.trim_end().strip_suffix('|')?;
+	let mut columns = Token::init(TokenType::TableColumns, String::new()).at(row.span.start);
+	let mut warnings = Vec::new();
+	let mut start = row.span.start + 1;
+	for cell in body.split('|') {
+		let (spec, attributes) = match cell.find('{') {
+			Some(x) => (cell[..x].trim(), cell[x..].trim()),
+			None => (cell.trim(), ""),
+		};
+		if spec.len() < 3 || !spec.contains('-') || !spec.chars().all(|x| x == '-' || x == ':') || (!attributes.is_empty() && !attributes.ends_with('}')) {
+			return None
+		}
+		let mut column = Token::init(TokenType::Put, spec.to_owned()).at(start);
+		column.span.end = start + cell.len();
+		if !attributes.is_empty() {
+			warnings.extend(Attributes::parse(attributes, start + cell.find('{').unwrap_or(0)).1);
+			column.attributes = attributes.to_owned();
+		}
+		columns.subtokens.push(column);
+		start += cell.len() + 1;
+	}
+	Some((columns, warnings))
+}
+
+/// A row like `|===|` starts the foot of a table.
+fn is_foot_separator(content: &str) -> bool {
+	match content.trim_end().strip_suffix('|') {
+		Some(body) => body.split('|').all(|x| !x.trim().is_empty() && x.trim().chars().all(|x| x == '=')),
+		None => false,
+	}
 }
 
-pub(crate) fn block_lexer(lines: &Vec<Vec<Token>>) -> Vec<Token>{
+/// Turns the colspan and rowspan of a cell starter into attributes, and closes the cell's attribute sequence.
+fn end_cell_starter(cell: &mut Token, col: &str, row: &str) {
+	let mut close_atter = !cell.attributes.is_empty();
+	if !col.is_empty() || !row.is_empty() {
+		if !close_atter {
+			cell.attributes += "{";
+			close_atter = true;
+		}
+		if !row.is_empty() {
+			cell.attributes += &(" rowspan=\"".to_owned() + row + "\"");
+		}
+		if !col.is_empty() {
+			cell.attributes += &(" colspan=\"".to_owned() + col + "\"");
+		}
+	}
+	if close_atter { cell.attributes += "}" }
+}
+
+/// Tokenizes every line of `input` and groups the lines into blocks. Lines inside fenced code blocks aren't tokenized, they're kept as they are.
+pub(crate) fn block_lexer(input: &str) -> (Vec<Token>, Vec<Diagnostic>) {
+	let lines: Vec<(usize, &str)> = input.lines().map(|x| (x.as_ptr() as usize - input.as_ptr() as usize, x)).collect();
+	lex_blocks(&lines, 0)
+}
+
+/// Groups lines into blocks. Each line comes with its position in the source, so that the lines of a blockquote can be lexed again without their `>`.
+fn lex_blocks(lines: &[(usize, &str)], depth: usize) -> (Vec<Token>, Vec<Diagnostic>) {
 	let mut blocks: Vec<Token> = Vec::new();
+	let mut warnings: Vec<Diagnostic> = Vec::new();
 	let mut current_block: Token;
 	let mut lists: Vec<Token> = Vec::new();
 	let mut table: Token = Token::init(TokenType::Table, String::new());
 	let mut next_attr: String = String::new();
-	for line in lines.iter() {
+	let mut fence: Option<(Token, usize)> = None; // The code block being read and the length of its fence
+	let mut lines = lines.iter().peekable();
+	while let Some(&(offset, text)) = lines.next() {
+		if let Some((code, len)) = &mut fence {
+			if text.trim_end().len() >= *len && text.trim_end().chars().all(|x| x == '`') {
+				code.span.end = offset + text.len();
+				blocks.push(code.clone());
+				fence = None;
+			} else {
+				code.content += text;
+				code.content.push('\n');
+				code.span.end = offset + text.len();
+			}
+			continue;
+		}
+		if let Some((mut code, len, mut warns)) = code_fence(text, offset) {
+			add_table(&mut blocks, &mut table);
+			add_lists(&mut blocks, &mut lists);
+			if code.attributes.is_empty() {
+				code.attributes = next_attr.clone();
+			}
+			next_attr = String::new();
+			warnings.append(&mut warns);
+			fence = Some((code, len));
+			continue;
+		}
+		if text.starts_with('>') && depth >= MAX_DEPTH {
+			warnings.push(Diagnostic::warning("nesting-too-deep", format!("Blocks can't be nested more than {} times, so this line was treated as text", MAX_DEPTH), offset..offset + text.len()));
+		} else if text.starts_with('>') {
+			add_table(&mut blocks, &mut table);
+			add_lists(&mut blocks, &mut lists);
+			let mut inner = vec![quote_line(offset, text)];
+			let mut end = offset + text.len();
+			while let Some(&(offset, text)) = lines.next_if(|(_, x)| x.starts_with('>')) {
+				inner.push(quote_line(offset, text));
+				end = offset + text.len();
+			}
+			let (mut quote, mut warns) = quote_block(&inner, depth + 1);
+			quote.span = offset..end;
+			quote.attributes = next_attr.clone();
+			next_attr = String::new();
+			warnings.append(&mut warns);
+			blocks.push(quote);
+			continue;
+		}
+		if let Some((definition, mut warns)) = link_definition(text, offset) {
+			add_table(&mut blocks, &mut table);
+			add_lists(&mut blocks, &mut lists);
+			warnings.append(&mut warns);
+			blocks.push(definition);
+			continue;
+		}
+		if text.trim() == "[[toc]]" {
+			add_table(&mut blocks, &mut table);
+			add_lists(&mut blocks, &mut lists);
+			let mut toc = Token::init(TokenType::Toc, String::new()).at(offset);
+			toc.span.end = offset + text.len();
+			toc.attributes = std::mem::take(&mut next_attr);
+			blocks.push(toc);
+			continue;
+		}
+		if let Some((label, rest)) = footnote_line(text) {
+			add_table(&mut blocks, &mut table);
+			add_lists(&mut blocks, &mut lists);
+			let (mut line, mut warns) = tokenize(rest, offset + text.len() - rest.len());
+			warnings.append(&mut warns);
+			let mut note = Token::init(TokenType::FootnoteDef, label.to_owned()).at(offset);
+			note.span.end = offset + text.len();
+			note.attributes = leading_attributes(&mut line);
+			note.subtokens = line;
+			blocks.push(item_body(note, &mut lines, depth, &mut warnings));
+			continue;
+		}
+		if let Some((class, rest)) = definition_line(text) {
+			add_table(&mut blocks, &mut table);
+			add_lists(&mut blocks, &mut lists);
+			let (mut line, mut warns) = tokenize(rest, offset + 2);
+			warnings.append(&mut warns);
+			let mut entry = Token::init(class, String::new()).at(offset);
+			entry.span.end = offset + text.len();
+			entry.attributes = leading_attributes(&mut line);
+			entry.subtokens = line;
+			let is_term = matches!(entry.class, TokenType::DefTerm);
+			if !is_term {
+				entry = item_body(entry, &mut lines, depth, &mut warnings);
+			}
+			match blocks.last_mut() {
+				Some(x) if matches!(x.class, TokenType::DefList) => x.subtokens.push(entry),
+				_ => {
+					if !is_term {
+						warnings.push(Diagnostic::warning("definition-without-term", "Definition list starts with a definition instead of a term".to_owned(), offset..offset + text.len()));
+					}
+					let mut list = Token::init_sub(TokenType::DefList, vec![entry], String::new());
+					list.attributes = std::mem::take(&mut next_attr);
+					blocks.push(list);
+				},
+			}
+			continue;
+		}
+		if let Some(rest) = figure_caption(text) {
+			add_table(&mut blocks, &mut table);
+			add_lists(&mut blocks, &mut lists);
+			match blocks.pop() {
+				Some(content) if matches!(content.class, TokenType::Image | TokenType::Table | TokenType::CodeBlock) => {
+					let (mut line, mut warns) = tokenize(rest, offset + text.len() - rest.len());
+					warnings.append(&mut warns);
+					let attributes = leading_attributes(&mut line);
+					let mut caption = Token::init_sub(TokenType::Caption, line, String::new()).at(offset);
+					caption.span.end = offset + text.len();
+					let mut figure = Token::init_sub(TokenType::Figure, vec![content, caption], String::new());
+					figure.attributes = attributes;
+					blocks.push(figure);
+					continue;
+				},
+				last => {
+					blocks.extend(last);
+					warnings.push(Diagnostic::warning("caption-without-figure", "Caption isn't right after an image, table or code block, so it was treated as text".to_owned(), offset..offset + text.len()));
+				},
+			}
+		}
+		let (line, mut warns) = tokenize(text, offset);
+		warnings.append(&mut warns);
 		match line.first() {
 			None => add_table(&mut blocks, &mut table),
 			Some(ftoken) => {
 				match ftoken.class {
 					TokenType::TableRow => {
+						add_lists(&mut blocks, &mut lists);
 						if table.subtokens.is_empty() {
 							table.attributes = next_attr.clone();
 							next_attr = String::new();
 						}
-						table.subtokens.push(table_parse(&ftoken.content));
+						let end = offset + text.len();
+						if let Some(rest) = ftoken.content.strip_prefix('+') {
+							let rest = rest.trim_start();
+							let (line, mut warns) = tokenize(rest, end - rest.len());
+							warnings.append(&mut warns);
+							if table.subtokens.iter().any(|x| matches!(x.class, TokenType::Caption)) {
+								warnings.push(Diagnostic::warning("duplicate-table-caption", "Table has more than one caption, only the first one is used".to_owned(), offset..end));
+							}
+							let mut caption = Token::init_sub(TokenType::Caption, line, String::new()).at(offset);
+							caption.span.end = end;
+							table.subtokens.push(caption);
+						} else if let Some((mut columns, mut warns)) = column_spec(ftoken) {
+							warnings.append(&mut warns);
+							if table.subtokens.iter().any(|x| matches!(x.class, TokenType::TableColumns)) {
+								warnings.push(Diagnostic::warning("duplicate-table-columns", "Table has more than one column specification, only the first one is used".to_owned(), offset..end));
+							}
+							columns.span.end = end;
+							table.subtokens.push(columns);
+						} else if is_foot_separator(&ftoken.content) {
+							let mut foot = Token::init(TokenType::TableFoot, String::new()).at(offset);
+							foot.span.end = end;
+							table.subtokens.push(foot);
+						} else {
+							let (mut row, mut warns) = table_parse(ftoken);
+							warnings.append(&mut warns);
+							for cell in row.subtokens.iter_mut().filter(|x| x.content.trim() == ":::") {
+								cell_blocks(cell, &mut lines, depth, &mut warnings);
+							}
+							table.subtokens.push(row);
+						}
 					},
 					TokenType::ListEl => {
 						add_table(&mut blocks, &mut table);
@@ -155,6 +398,8 @@ pub(crate) fn block_lexer(lines: &Vec<Vec<Token>>) -> Vec<Token>{
 							ft.subtokens = line[1..].to_vec();
 							ft
 						};
+						let fltoken = item_body(fltoken, &mut lines, depth, &mut warnings);
+						check_list_depth(&mut warnings, &lists, &fltoken);
 						match lists.last_mut() {
 							None => {
 								let mut new_sublist = Token::init_sub(TokenType::UList, vec![fltoken.to_owned()], fltoken.content.to_owned());
@@ -165,9 +410,9 @@ pub(crate) fn block_lexer(lines: &Vec<Vec<Token>>) -> Vec<Token>{
 							Some(x) => {
 								match x.class {
 									TokenType::UList => {
-										if get_list_depth(&x) == get_list_depth(&fltoken) {
+										if get_list_depth(x) == get_list_depth(&fltoken) {
 											x.subtokens.push(fltoken.to_owned());
-										} else if get_list_depth(&x) < get_list_depth(&fltoken) {
+										} else if get_list_depth(x) < get_list_depth(&fltoken) {
 											let mut new_sublist = Token::init_sub(TokenType::UList, vec![fltoken.to_owned()], fltoken.content.to_owned());
 											new_sublist.attributes = next_attr.clone();	
 											lists.push(new_sublist);
@@ -178,10 +423,9 @@ pub(crate) fn block_lexer(lines: &Vec<Vec<Token>>) -> Vec<Token>{
 											lists.push(new_sublist);
 										}
 									},
-									TokenType::OList => {
+									_ => {
 										lists.push(Token::init_sub(TokenType::UList, vec![fltoken.to_owned()], fltoken.content.to_owned()));
 									},
-									_ => panic!("This wasn't supposed to be possible at all"),
 								}
 							},
 						}
@@ -193,6 +437,8 @@ pub(crate) fn block_lexer(lines: &Vec<Vec<Token>>) -> Vec<Token>{
 							ft.subtokens = line[1..].to_vec();
 							ft
 						};
+						let fltoken = item_body(fltoken, &mut lines, depth, &mut warnings);
+						check_list_depth(&mut warnings, &lists, &fltoken);
 						match lists.last_mut() {
 							None => {
 								let mut new_sublist = Token::init_sub(TokenType::OList, vec![fltoken.to_owned()], fltoken.content.to_owned());
@@ -203,9 +449,9 @@ pub(crate) fn block_lexer(lines: &Vec<Vec<Token>>) -> Vec<Token>{
 							Some(x) => {
 								match x.class {
 									TokenType::OList => {
-										if get_list_depth(&x) == get_list_depth(&fltoken) {
+										if get_list_depth(x) == get_list_depth(&fltoken) {
 											x.subtokens.push(fltoken.to_owned());
-										} else if get_list_depth(&x) < get_list_depth(&fltoken) {
+										} else if get_list_depth(x) < get_list_depth(&fltoken) {
 											let mut new_sublist = Token::init_sub(TokenType::OList, vec![fltoken.to_owned()], fltoken.content.to_owned());
 											new_sublist.attributes = next_attr.clone();	
 											lists.push(new_sublist);
@@ -216,30 +462,26 @@ pub(crate) fn block_lexer(lines: &Vec<Vec<Token>>) -> Vec<Token>{
 											lists.push(new_sublist);
 										}
 									},
-									TokenType::UList => {
+									_ => {
 										lists.push(Token::init_sub(TokenType::OList, vec![fltoken.to_owned()], fltoken.content.to_owned()));
 									},
-									_ => panic!("This wasn't supposed to be possible at all"),
 								}
 							},
 						}
 					},
 					TokenType::Html => {
 						add_table(&mut blocks, &mut table);
-						if !lists.is_empty() {
-							push_token(&mut blocks, &Token::init_sub(TokenType::ListBlock, lists.clone(), String::new()));
-						}
+						add_lists(&mut blocks, &mut lists);
 						current_block = ftoken.clone();
 						current_block.subtokens = line[1..].to_vec();
 						push_token(&mut blocks, &current_block);
 					}
 					TokenType::Attr => {
-						if !lists.is_empty() {
-							push_token(&mut blocks, &Token::init_sub(TokenType::ListBlock, lists.clone(), String::new()));
-							lists = Vec::new();
-						}
+						add_lists(&mut blocks, &mut lists);
 						if line.len() > 1 {
+							add_table(&mut blocks, &mut table);
 							current_block = Token::n_para();
+							current_block.span = line[0].span.clone();
 							current_block.attributes = line[0].content.to_owned();
 							current_block.subtokens = line[1..].to_vec();
 							push_token(&mut blocks, &current_block);
@@ -249,21 +491,16 @@ pub(crate) fn block_lexer(lines: &Vec<Vec<Token>>) -> Vec<Token>{
 					},
 					TokenType::Header => {
 						add_table(&mut blocks, &mut table);
-						if !lists.is_empty() {
-							push_token(&mut blocks, &Token::init_sub(TokenType::ListBlock, lists.clone(), String::new()));
-							lists = Vec::new();
-						}
+						add_lists(&mut blocks, &mut lists);
 						current_block = ftoken.clone();
 						current_block.subtokens = line[1..].to_vec();
 						push_token(&mut blocks, &current_block);
 					},
 					TokenType::Image => {
 						add_table(&mut blocks, &mut table);
-						if !lists.is_empty() {
-							push_token(&mut blocks, &Token::init_sub(TokenType::ListBlock, lists.clone(), String::new()));
-							lists = Vec::new();
-						}
+						add_lists(&mut blocks, &mut lists);
 						if line.len() > 1 {
+							add_table(&mut blocks, &mut table);
 							current_block = Token::n_para();
 							current_block.subtokens = line.clone();
 							push_token(&mut blocks, &current_block);
@@ -274,22 +511,20 @@ pub(crate) fn block_lexer(lines: &Vec<Vec<Token>>) -> Vec<Token>{
 					}
 					_ => {
 						add_table(&mut blocks, &mut table);
-						if !lists.is_empty() {
-							push_token(&mut blocks, &Token::init_sub(TokenType::ListBlock, lists.clone(), String::new()));
-							lists = Vec::new();
-						}
+						add_lists(&mut blocks, &mut lists);
 						current_block = Token::n_para();
 						current_block.subtokens = line.clone();
 						match blocks.last_mut() {
 							None => push_token(&mut blocks, &current_block),
+							Some(x) if matches!(x.class, TokenType::CodeBlock | TokenType::Quote | TokenType::DefList | TokenType::FootnoteDef | TokenType::Toc | TokenType::LinkDef | TokenType::Figure) => push_token(&mut blocks, &current_block),
 							Some(x) => {
-								let first_char = current_block.subtokens.first_mut().expect("An empty string got to the paragraph parser.");
-								if &first_char.content[0..1] == " " {
-									x.subtokens.push(Token::init(TokenType::LineBreak, "\n".to_owned()));
-									first_char.content = "\n".to_owned() + &first_char.content[1..];
-									x.subtokens.append(&mut current_block.subtokens);
-								} else {
-									push_token(&mut blocks, &current_block);
+								match current_block.subtokens.first_mut() {
+									Some(first_char) if first_char.content.starts_with(' ') => {
+										x.subtokens.push(Token::init(TokenType::LineBreak, "\n".to_owned()).at(first_char.span.start));
+										first_char.content = "\n".to_owned() + &first_char.content[1..];
+										x.subtokens.append(&mut current_block.subtokens);
+									},
+									_ => push_token(&mut blocks, &current_block),
 								}
 							},
 						}
@@ -298,16 +533,278 @@ pub(crate) fn block_lexer(lines: &Vec<Vec<Token>>) -> Vec<Token>{
 			}
 		}
 	}
+	if let Some((code, _)) = fence {
+		warnings.push(Diagnostic::warning("unclosed-code-block", "Code block isn't closed, so it runs until the end of the document".to_owned(), code.span.clone()));
+		blocks.push(code);
+	}
 	add_table(&mut blocks, &mut table);
-	if !lists.is_empty() {
-		push_token(&mut blocks, &Token::init_sub(TokenType::ListBlock, lists.clone(), String::new()));
+	add_lists(&mut blocks, &mut lists);
+	for block in blocks.iter_mut() {
+		cover_spans(block);
+	}
+	(blocks, warnings)
+}
+
+/// Takes the content of a cell written as `:::`, from the lines after its row between two lines of three or more `:`. The closing line has to be as long as the opening one, so cells inside such a cell can use a different length. The content is lexed as blocks and kept as an `ItemBody` token instead of the cell's text.
+fn cell_blocks(cell: &mut Token, lines: &mut Peekable<Iter<(usize, &str)>>, depth: usize, warnings: &mut Vec<Diagnostic>) {
+	let fence = |text: &str| text.trim_end().len() >= 3 && text.trim_end().chars().all(|x| x == ':');
+	cell.subtokens.clear();
+	let Some(&(start, open)) = lines.next_if(|(_, x)| fence(x)) else {
+		warnings.push(Diagnostic::warning("missing-cell-content", "Cell is marked with ::: but there's no line of ::: after its row, so it was left empty".to_owned(), cell.span.clone()));
+		return
+	};
+	let len = open.trim_end().len();
+	let mut content: Vec<(usize, &str)> = Vec::new();
+	let mut end = start + open.len();
+	let mut closed = false;
+	for &(offset, text) in lines.by_ref() {
+		end = offset + text.len();
+		if fence(text) && text.trim_end().len() == len {
+			closed = true;
+			break;
+		}
+		content.push((offset, text));
+	}
+	if !closed {
+		warnings.push(Diagnostic::warning("unclosed-cell", "Cell content isn't closed with a line of :::, so it runs until the end of the document".to_owned(), start..end));
+	}
+	if depth >= MAX_DEPTH {
+		warnings.push(Diagnostic::warning("nesting-too-deep", format!("Blocks can't be nested more than {} times, so these lines were left out", MAX_DEPTH), start..end));
+		return
+	}
+	let (blocks, mut warns) = lex_blocks(&content, depth + 1);
+	warnings.append(&mut warns);
+	let mut body = Token::init_sub(TokenType::ItemBody, blocks, String::new()).at(start);
+	body.span.end = end;
+	cell.subtokens.push(body);
+}
+
+/// Takes the lines after a list item that belong to it: indented lines, and blank lines followed by more indented lines. They're lexed as blocks of their own, with their indentation removed. Paragraphs right after the item, before any blank line, continue its text after a line break like the lines of a paragraph do. Everything else is kept as an `ItemBody` token after the item's inline tokens.
+fn item_body(mut item: Token, lines: &mut Peekable<Iter<(usize, &str)>>, depth: usize, warnings: &mut Vec<Diagnostic>) -> Token {
+	let mut body: Vec<(usize, &str)> = Vec::new();
+	loop {
+		let mut ahead = lines.clone();
+		let mut blanks = 0;
+		while ahead.next_if(|(_, x)| x.trim().is_empty()).is_some() {
+			blanks += 1;
+		}
+		match ahead.peek() {
+			Some((_, x)) if x.starts_with([' ', '\t']) => body.extend(lines.by_ref().take(blanks + 1).copied()),
+			_ => break,
+		}
+	}
+	if body.is_empty() {
+		return item
+	}
+	if depth >= MAX_DEPTH {
+		let (start, _) = body[0];
+		let (end, text) = body[body.len() - 1];
+		warnings.push(Diagnostic::warning("nesting-too-deep", format!("Blocks can't be nested more than {} times, so these lines were left out", MAX_DEPTH), start..end + text.len()));
+		return item
+	}
+	let indent = body.iter().map(|(_, x)| indentation(x)).find(|x| *x > 0).unwrap_or(0);
+	let first_blank = body.iter().find(|(_, x)| x.trim().is_empty()).map(|(x, _)| *x).unwrap_or(usize::MAX);
+	let body: Vec<(usize, &str)> = body.iter().map(|&(offset, text)| {
+		let cut = indentation(text).min(indent);
+		(offset + cut, &text[cut..])
+	}).collect();
+	let (blocks, mut warns) = lex_blocks(&body, depth + 1);
+	warnings.append(&mut warns);
+	let mut blocks = blocks.into_iter().peekable();
+	while let Some(para) = blocks.next_if(|x| matches!(x.class, TokenType::Para) && x.attributes.is_empty() && x.span.start < first_blank) {
+		item.subtokens.push(Token::init(TokenType::LineBreak, "\n".to_owned()).at(para.span.start));
+		item.subtokens.extend(para.subtokens);
+	}
+	let rest: Vec<Token> = blocks.collect();
+	if !rest.is_empty() {
+		item.subtokens.push(Token::init_sub(TokenType::ItemBody, rest, String::new()));
+	}
+	item
+}
+
+fn indentation(text: &str) -> usize {
+	text.len() - text.trim_start_matches([' ', '\t']).len()
+}
+
+/// Takes the attribute sequence at the start of a line, for the elements whose attributes go right after their marker. The spaces after the sequence are removed too.
+fn leading_attributes(line: &mut Vec<Token>) -> String {
+	if !matches!(line.first(), Some(x) if matches!(x.class, TokenType::Attr)) {
+		return String::new()
+	}
+	let attributes = line.remove(0).content;
+	match line.first_mut() {
+		Some(first) if matches!(first.class, TokenType::Put) => first.content = first.content.trim_start().to_owned(),
+		_ => (),
+	}
+	attributes
+}
+
+/// Footnotes are defined with a line like `[^label]: text`.
+fn footnote_line(text: &str) -> Option<(&str, &str)> {
+	let rest = text.strip_prefix("[^")?;
+	let end = rest.find("]:")?;
+	let label = &rest[..end];
+	if label.is_empty() || label.contains(char::is_whitespace) {
+		return None
+	}
+	let rest = &rest[end + 2..];
+	Some((label, rest.strip_prefix(' ').unwrap_or(rest)))
+}
+
+/// Reads a reference link definition like `[label]: destination "title" {attributes}`. The title and attributes are optional, and the destination can be written between `<` and `>`. The destination and title are kept as the subtokens of the definition.
+fn link_definition(text: &str, offset: usize) -> Option<(Token, Vec<Diagnostic>)> {
+	let rest = text.strip_prefix('[')?;
+	let end = rest.find("]:")?;
+	let label = &rest[..end];
+	if label.trim().is_empty() || label.starts_with('^') || label.contains(['[', ']']) {
+		return None
+	}
+	let body = rest[end + 2..].trim_start();
+	if body.is_empty() {
+		return None
+	}
+	let position = |x: &str| offset + (x.as_ptr() as usize - text.as_ptr() as usize);
+	let mut warnings = Vec::new();
+	let (destination, rest) = match body.strip_prefix('<') {
+		Some(x) => match x.find('>') {
+			Some(end) => (&x[..end], &x[end + 1..]),
+			None => {
+				warnings.push(Diagnostic::warning("unclosed-link-destination", "Link destination isn't closed with a >".to_owned(), position(body)..offset + text.len()));
+				(x, "")
+			},
+		},
+		None => body.split_at(body.find(|x: char| x.is_whitespace() || x == '{').unwrap_or(body.len())),
+	};
+	let mut definition = Token::init(TokenType::LinkDef, label.to_owned()).at(offset);
+	definition.span.end = offset + text.len();
+	definition.subtokens.push(Token::init(TokenType::Put, destination.to_owned()).at(position(destination)));
+	let mut rest = rest.trim_start();
+	if let Some(quote) = rest.chars().next().filter(|x| matches!(x, '"' | '\'' | '(')) {
+		let close = if quote == '(' { ')' } else { quote };
+		let inner = &rest[1..];
+		let (title, after) = match inner.find(close) {
+			Some(end) => (&inner[..end], &inner[end + 1..]),
+			None => {
+				warnings.push(Diagnostic::warning("unclosed-link-title", format!("Link title isn't closed with a {}", close), position(rest)..offset + text.len()));
+				(inner, "")
+			},
+		};
+		definition.subtokens.push(Token::init(TokenType::Put, title.to_owned()).at(position(title)));
+		rest = after.trim_start();
+	}
+	if rest.starts_with('{') {
+		let attributes = rest.trim_end();
+		warnings.append(&mut Attributes::parse(attributes, position(attributes)).1);
+		definition.attributes = attributes.to_owned();
+	} else if !rest.is_empty() {
+		warnings.push(Diagnostic::warning("link-definition-text", "Unexpected text after a link definition, so it was ignored".to_owned(), position(rest)..offset + text.len()));
+	}
+	Some((definition, warnings))
+}
+
+/// The caption of a figure is written on a line starting with `!!`, after the image, table or code block it describes. Its attributes go right after the `!!`.
+fn figure_caption(text: &str) -> Option<&str> {
+	let rest = text.strip_prefix("!!")?;
+	match rest.chars().next() {
+		None | Some('{') => Some(rest),
+		Some(x) if x.is_whitespace() => Some(rest.trim_start()),
+		_ => None,
+	}
+}
+
+/// Lines of definition lists start with `; ` for terms and `: ` for definitions.
+fn definition_line(text: &str) -> Option<(TokenType, &str)> {
+	match text.strip_prefix("; ") {
+		Some(rest) => Some((TokenType::DefTerm, rest)),
+		None => text.strip_prefix(": ").map(|rest| (TokenType::DefDesc, rest)),
+	}
+}
+
+/// Removes the `>` that starts a line of a blockquote, and the space after it.
+fn quote_line(offset: usize, text: &str) -> (usize, &str) {
+	let rest = &text[1..];
+	let rest = rest.strip_prefix(' ').unwrap_or(rest);
+	(offset + text.len() - rest.len(), rest)
+}
+
+/// Lexes the lines of a blockquote as blocks of their own. If the last line starts with `-- `, it's the attribution of the quote, and it's kept as a `Citation` token after the blocks.
+fn quote_block(lines: &[(usize, &str)], depth: usize) -> (Token, Vec<Diagnostic>) {
+	let mut citation = None;
+	let mut content = lines;
+	let mut name_warnings = Vec::new();
+	if let Some(&(offset, text)) = lines.last() {
+		if let Some(name) = text.strip_prefix("-- ").or(text.strip_prefix("— ")) {
+			let name_offset = offset + text.len() - name.len();
+			let (name, warns) = tokenize(name, name_offset);
+			name_warnings = warns;
+			let mut token = Token::init_sub(TokenType::Citation, name, String::new()).at(offset);
+			token.span.end = offset + text.len();
+			citation = Some(token);
+			content = &lines[..lines.len() - 1];
+		}
+	}
+	let (mut blocks, mut warnings) = lex_blocks(content, depth);
+	blocks.extend(citation);
+	warnings.append(&mut name_warnings);
+	(Token::init_sub(TokenType::Quote, blocks, String::new()), warnings)
+}
+
+/// Reads the line that opens a fenced code block, like ```` ```rust{#id} ````. The language, if there is one, is kept as the only subtoken of the block.
+fn code_fence(text: &str, offset: usize) -> Option<(Token, usize, Vec<Diagnostic>)> {
+	let len = text.len() - text.trim_start_matches('`').len();
+	let info = text[len..].trim();
+	// Otherwise a line of inline code like `` ```a``` `` would open a block
+	if len < 3 || info.contains('`') {
+		return None
+	}
+	let (language, attributes) = match info.find('{') {
+		Some(x) => (info[..x].trim_end(), &info[x..]),
+		None => (info, ""),
+	};
+	let mut code = Token::init(TokenType::CodeBlock, String::new()).at(offset);
+	code.span.end = offset + text.len();
+	let mut warnings = Vec::new();
+	if !attributes.is_empty() {
+		let attr_offset = offset + (attributes.as_ptr() as usize - text.as_ptr() as usize);
+		warnings = Attributes::parse(attributes, attr_offset).1;
+		code.attributes = attributes.to_owned();
+	}
+	if !language.is_empty() {
+		let language_offset = offset + (language.as_ptr() as usize - text.as_ptr() as usize);
+		code.subtokens.push(Token::init(TokenType::Put, language.to_owned()).at(language_offset));
+	}
+	Some((code, len, warnings))
+}
+
+/// Makes the span of a token cover all of its subtokens. Tokens made by the block lexer don't come from any single place in the source, so they only get a span this way.
+fn cover_spans(token: &mut Token) {
+	let mut span = if token.span.is_empty() { None } else { Some(token.span.clone()) };
+	for subtoken in token.subtokens.iter_mut() {
+		cover_spans(subtoken);
+		if subtoken.span.is_empty() {
+			continue;
+		}
+		span = match span {
+			None => Some(subtoken.span.clone()),
+			Some(x) => Some(x.start.min(subtoken.span.start)..x.end.max(subtoken.span.end)),
+		};
+	}
+	if let Some(span) = span {
+		token.span = span;
+	}
+}
+
+fn check_list_depth(warnings: &mut Vec<Diagnostic>, lists: &[Token], item: &Token) {
+	let last_depth = lists.last().map(get_list_depth).unwrap_or(0);
+	let depth = get_list_depth(item);
+	if depth > last_depth + 1 {
+		warnings.push(Diagnostic::warning("list-depth-jump", format!("List element is {} levels deeper than the previous one", depth - last_depth), item.span.clone()));
 	}
-	blocks
 }
 
 pub(crate) fn get_list_depth(token: &Token) -> usize {
 	match token.class {
-		TokenType::UList | TokenType::ListEl | TokenType::OList | TokenType::NumberedListEl => token.content.len() - 1,
-		_ => panic!("Passed a non-list token to get_list_depth"),
+		TokenType::UList | TokenType::ListEl | TokenType::OList | TokenType::NumberedListEl => token.content.len().saturating_sub(1),
+		_ => 0,
 	}
 }
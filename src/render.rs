@@ -0,0 +1,198 @@
+use crate::ast::{Block, BlockKind, DefinitionEntry, Document, Footnote, Image, Inline, InlineKind, ListItem, TableColumn, TableRow, TocEntry};
+use crate::html;
+use crate::syntax::Options;
+
+/// Turns a parsed document into some output format, with a method for every kind of node.
+///
+/// Every method renders HTML by default, and renders the nodes inside it through `self`, so a renderer only has to override the nodes it wants to change: overriding `link` changes every link, even the ones inside bold text or table cells. Renderers for other formats override all of them. `block` and `inline` pick the method for each node, and can be overridden to handle every node in one place.
+pub trait Renderer {
+	/// The options the document was parsed with. The HTML methods use them for safe mode, source lines and lazy images.
+	fn options(&self) -> &Options;
+
+	/// Renders the blocks of the document, followed by its footnotes.
+	fn document(&mut self, document: &Document) -> String {
+		self.blocks(&document.blocks) + &self.footnotes(&document.footnotes)
+	}
+
+	fn blocks(&mut self, blocks: &[Block]) -> String {
+		let mut out = String::new();
+		for block in blocks.iter() {
+			out += &self.block(block);
+		}
+		out
+	}
+
+	fn block(&mut self, block: &Block) -> String {
+		match &block.kind {
+			BlockKind::Paragraph(content) => self.paragraph(block, content),
+			BlockKind::Header { level, content } => self.header(block, *level, content),
+			BlockKind::Image(image) => self.image_block(block, image),
+			BlockKind::Html { tag, content } => self.html_block(block, tag, content),
+			BlockKind::Code { language, content } => self.code_block(block, language.as_deref(), content),
+			BlockKind::Quote { content, attribution } => self.quote(block, content, attribution.as_deref()),
+			BlockKind::Table { caption, columns, head, body, foot } => self.table(block, caption.as_deref(), columns, head, body, foot),
+			BlockKind::Figure { number, content, caption } => self.figure(block, *number, content, caption),
+			BlockKind::DefinitionList { entries } => self.definition_list(block, entries),
+			BlockKind::Toc { entries } => self.toc(block, entries),
+			BlockKind::List { ordered, items } => self.list(block, *ordered, items),
+		}
+	}
+
+	fn inlines(&mut self, inlines: &[Inline]) -> String {
+		let mut out = String::new();
+		for inline in inlines.iter() {
+			out += &self.inline(inline);
+		}
+		out
+	}
+
+	fn inline(&mut self, inline: &Inline) -> String {
+		match &inline.kind {
+			InlineKind::Text(text) => self.text(inline, text),
+			InlineKind::Raw(text) => self.raw(inline, text),
+			InlineKind::Html(text) => self.html(inline, text),
+			InlineKind::Code(text) => self.code(inline, text),
+			InlineKind::LineBreak => self.line_break(inline),
+			InlineKind::Image(image) => self.image(inline, image),
+			InlineKind::Link { destination, title, content } => self.link(inline, destination.as_deref(), title.as_deref(), content),
+			InlineKind::LinkRef { label, content } => self.link_ref(inline, label, content),
+			InlineKind::FootnoteRef { label, number, reference } => self.footnote_ref(inline, label, *number, *reference),
+			InlineKind::Bold(content) => self.bold(inline, content),
+			InlineKind::Strong(content) => self.strong(inline, content),
+			InlineKind::Italic(content) => self.italic(inline, content),
+			InlineKind::Emphasis(content) => self.emphasis(inline, content),
+			InlineKind::Sub(content) => self.sub(inline, content),
+			InlineKind::Sup(content) => self.sup(inline, content),
+			InlineKind::Span(content) => self.span(inline, content),
+			InlineKind::Strike(content) => self.strike(inline, content),
+			InlineKind::Under(content) => self.under(inline, content),
+		}
+	}
+
+	/// Renders the footnotes of the document, which come after all of its blocks. Documents without footnotes still call this, with an empty list.
+	fn footnotes(&mut self, notes: &[Footnote]) -> String {
+		html::footnotes(self, notes)
+	}
+
+	fn paragraph(&mut self, block: &Block, content: &[Inline]) -> String {
+		html::paragraph(self, block, content)
+	}
+
+	fn header(&mut self, block: &Block, level: usize, content: &[Inline]) -> String {
+		html::header(self, block, level, content)
+	}
+
+	/// An image that is a block of its own. Images inside text go through `image`.
+	fn image_block(&mut self, block: &Block, image: &Image) -> String {
+		html::image(self, image, &block.attributes, Some(block.line)) + "\n"
+	}
+
+	fn html_block(&mut self, _block: &Block, tag: &str, content: &[Inline]) -> String {
+		html::html_block(self, tag, content)
+	}
+
+	fn code_block(&mut self, block: &Block, language: Option<&str>, content: &str) -> String {
+		html::code_block(self, block, language, content)
+	}
+
+	fn quote(&mut self, block: &Block, content: &[Block], attribution: Option<&[Inline]>) -> String {
+		html::quote(self, block, content, attribution)
+	}
+
+	fn table(&mut self, block: &Block, caption: Option<&[Inline]>, columns: &[TableColumn], head: &[TableRow], body: &[TableRow], foot: &[TableRow]) -> String {
+		html::table(self, block, caption, columns, [head, body, foot])
+	}
+
+	fn figure(&mut self, block: &Block, number: usize, content: &Block, caption: &[Inline]) -> String {
+		html::figure(self, block, number, content, caption)
+	}
+
+	fn definition_list(&mut self, block: &Block, entries: &[DefinitionEntry]) -> String {
+		html::definition_list(self, block, entries)
+	}
+
+	fn toc(&mut self, block: &Block, entries: &[TocEntry]) -> String {
+		html::toc(self, block, entries)
+	}
+
+	fn list(&mut self, block: &Block, ordered: bool, items: &[ListItem]) -> String {
+		html::list(self, block, ordered, items)
+	}
+
+	fn text(&mut self, _inline: &Inline, text: &str) -> String {
+		html::encode_text(text)
+	}
+
+	/// Text written between `\=` and `=`, which goes to the output as it is.
+	fn raw(&mut self, _inline: &Inline, text: &str) -> String {
+		html::safe_html(text, false, self.options())
+	}
+
+	/// An HTML tag written in the text.
+	fn html(&mut self, _inline: &Inline, text: &str) -> String {
+		html::safe_html(text, true, self.options())
+	}
+
+	fn code(&mut self, inline: &Inline, text: &str) -> String {
+		html::code(self, inline, text)
+	}
+
+	fn line_break(&mut self, _inline: &Inline) -> String {
+		"</br>".to_owned()
+	}
+
+	fn image(&mut self, inline: &Inline, image: &Image) -> String {
+		html::image(self, image, &inline.attributes, None)
+	}
+
+	/// A link. `destination` is `None` for links like `[https://example.com]`, whose text is also where they go.
+	fn link(&mut self, inline: &Inline, destination: Option<&str>, title: Option<&str>, content: &[Inline]) -> String {
+		html::link(self, inline, destination, title, content)
+	}
+
+	/// A reference link whose label isn't defined anywhere.
+	fn link_ref(&mut self, _inline: &Inline, label: &str, content: &[Inline]) -> String {
+		"[".to_owned() + &self.inlines(content) + "][" + &html::encode_text(label) + "]"
+	}
+
+	/// A reference to a footnote. `number` is `None` if the footnote isn't defined, and `reference` counts the references to the same footnote from 1.
+	fn footnote_ref(&mut self, _inline: &Inline, label: &str, number: Option<usize>, reference: usize) -> String {
+		html::footnote_ref(label, number, reference)
+	}
+
+	fn bold(&mut self, inline: &Inline, content: &[Inline]) -> String {
+		html::wrap(self, "b", inline, content)
+	}
+
+	fn strong(&mut self, inline: &Inline, content: &[Inline]) -> String {
+		html::wrap(self, "strong", inline, content)
+	}
+
+	fn italic(&mut self, inline: &Inline, content: &[Inline]) -> String {
+		html::wrap(self, "i", inline, content)
+	}
+
+	fn emphasis(&mut self, inline: &Inline, content: &[Inline]) -> String {
+		html::wrap(self, "em", inline, content)
+	}
+
+	fn sub(&mut self, inline: &Inline, content: &[Inline]) -> String {
+		html::wrap(self, "sub", inline, content)
+	}
+
+	fn sup(&mut self, inline: &Inline, content: &[Inline]) -> String {
+		html::wrap(self, "sup", inline, content)
+	}
+
+	fn span(&mut self, inline: &Inline, content: &[Inline]) -> String {
+		html::wrap(self, "span", inline, content)
+	}
+
+	fn strike(&mut self, inline: &Inline, content: &[Inline]) -> String {
+		html::wrap(self, "del", inline, content)
+	}
+
+	fn under(&mut self, inline: &Inline, content: &[Inline]) -> String {
+		html::wrap(self, "u", inline, content)
+	}
+}
@@ -0,0 +1,119 @@
+use crate::attributes::Attributes;
+
+/// Attributes whose values are URLs, so they go through the same checks as link and image destinations.
+const URL_ATTRIBUTES: &[&str] = &["href", "src", "cite", "action", "formaction", "poster", "background", "xlink:href"];
+
+/// What to do with raw HTML that isn't allowed.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum RawHtml {
+	/// Show it as text.
+	#[default]
+	Escape,
+	/// Leave it out of the output.
+	Drop,
+}
+
+/// Rules for rendering documents that can't be trusted, like comments written by users.
+///
+/// Raw HTML is only let through if it's a tag in `allowed_tags`, and the attributes of every element, whether they come from HTML or from an attribute sequence, are filtered down to `allowed_attributes`. Event handlers like `onclick` are always removed, and links, images and URL attributes that use a `javascript:` or `vbscript:` URL, or a `data:` URL that isn't an image, are dropped.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SafeMode {
+	pub raw_html: RawHtml,
+	/// Lowercase names of the HTML tags that can be written in the document.
+	pub allowed_tags: Vec<String>,
+	/// Lowercase names of the attributes that elements can have, besides their id and classes.
+	pub allowed_attributes: Vec<String>,
+	/// Whether elements can have a `style` attribute.
+	pub allow_style: bool,
+}
+
+impl Default for SafeMode {
+	fn default() -> Self {
+		let tags = ["a", "abbr", "b", "bdi", "bdo", "blockquote", "br", "cite", "code", "dd", "del", "details", "dfn", "div", "dl", "dt", "em", "figcaption", "figure", "hr", "i", "ins", "kbd", "li", "mark", "ol", "p", "pre", "q", "s", "samp", "small", "span", "strong", "sub", "summary", "sup", "time", "u", "ul", "var", "wbr"];
+		let attributes = ["alt", "cite", "colspan", "datetime", "dir", "height", "href", "lang", "loading", "open", "rel", "reversed", "rowspan", "sizes", "src", "start", "title", "width"];
+		Self {
+			raw_html: RawHtml::Escape,
+			allowed_tags: tags.iter().map(|x| x.to_string()).collect(),
+			allowed_attributes: attributes.iter().map(|x| x.to_string()).collect(),
+			allow_style: false,
+		}
+	}
+}
+
+impl SafeMode {
+	pub fn allows_url(&self, url: &str, image: bool) -> bool {
+		// Browsers ignore whitespace and control characters inside a scheme, so they can't be used to sneak one past this
+		let url: String = url.chars().filter(|x| !x.is_whitespace() && !x.is_control()).collect::<String>().to_lowercase();
+		if url.starts_with("javascript:") || url.starts_with("vbscript:") {
+			return false
+		}
+		!url.starts_with("data:") || (image && url.starts_with("data:image/"))
+	}
+
+	pub fn allows_attribute(&self, key: &str, value: Option<&str>) -> bool {
+		let key = key.to_lowercase();
+		if key.starts_with("on") {
+			return false
+		}
+		if key == "style" {
+			return self.allow_style
+		}
+		if key == "id" || key == "class" {
+			return true
+		}
+		if !self.allowed_attributes.contains(&key) {
+			return false
+		}
+		match value {
+			Some(value) if URL_ATTRIBUTES.contains(&key.as_str()) => self.allows_url(value, key == "src"),
+			_ => true,
+		}
+	}
+
+	/// Removes every attribute that isn't allowed.
+	pub fn attributes(&self, attributes: &Attributes) -> Attributes {
+		let mut out = attributes.clone();
+		out.pairs.retain(|(key, value)| self.allows_attribute(key, value.as_deref()));
+		out
+	}
+
+	/// Rebuilds a single raw HTML tag like `<b class="x">` or `</b>` with only its allowed attributes. Returns `None` if the tag isn't allowed at all.
+	pub fn html_tag(&self, tag: &str) -> Option<String> {
+		let inner = tag.strip_prefix('<')?.strip_suffix('>')?;
+		let (closing, inner) = match inner.strip_prefix('/') {
+			Some(x) => (true, x),
+			None => (false, inner),
+		};
+		let name_len = inner.find(|x: char| !x.is_ascii_alphanumeric()).unwrap_or(inner.len());
+		let name = inner[..name_len].to_lowercase();
+		if name.is_empty() || !self.allowed_tags.contains(&name) {
+			return None
+		}
+		if closing {
+			return if inner[name_len..].trim().is_empty() { Some("</".to_owned() + &name + ">") } else { None }
+		}
+		let rest = inner[name_len..].trim_end();
+		let (self_closing, rest) = match rest.strip_suffix('/') {
+			Some(x) => (true, x),
+			None => (false, rest),
+		};
+		if !rest.is_empty() && !rest.starts_with(char::is_whitespace) {
+			return None
+		}
+		let attributes = self.attributes(&Attributes::parse(rest, 0).0);
+		let mut out = "<".to_owned() + &name;
+		let attr = crate::html::render_attr(&attributes);
+		if !attr.is_empty() {
+			out += &(" ".to_owned() + &attr);
+		}
+		Some(out + if self_closing { "/>" } else { ">" })
+	}
+
+	/// What raw HTML that isn't allowed turns into.
+	pub fn disallowed_html(&self, html: &str) -> String {
+		match self.raw_html {
+			RawHtml::Escape => crate::html::encode_text(html),
+			RawHtml::Drop => String::new(),
+		}
+	}
+}
@@ -1,239 +1,638 @@
-use crate::lexer::tokenize;
+use crate::ast::{plain_text, Alignment, Attributes, Block, BlockKind, DefinitionEntry, Document, Footnote, Image, Inline, InlineKind, ListItem, TableCell, TableColumn, TableRow};
+use crate::tables::check_tables;
+use crate::autolink::autolink;
+use crate::diagnostic::Diagnostic;
+use crate::html;
+use crate::images::{parse_image, ImageResolver};
 use crate::multiline_lexer::block_lexer;
-use crate::lexer::Token;
+use crate::lexer::{footnote_label, Token};
 use crate::lexer::TokenType;
 use crate::multiline_lexer::get_list_depth;
-use htmlentity::entity::*;
-
-pub(crate) fn parse_attr(inp: &str) -> String {
-	if inp == "{}" || inp == "" { 
-		return String::new()
-	}
-	let input = &inp[1..&inp.len()-1];
-	let mut id = String::new();
-	let mut class = String::new();
-	let mut current_type = "none";
-	let mut everything_else = String::new();
-	let mut out = String::new();
-	for (_, cha) in input.char_indices() {
-		match current_type {
-			"none" => {
-				match cha {
-					'.' => current_type = "class",
-					'#' => {
-						id = String::new();
-						current_type = "id";
-					},
-					_ => everything_else += &cha.to_string(),
-				}
+use crate::sanitize::SafeMode;
+use crate::toc;
+use std::cell::RefCell;
+use std::fmt;
+use std::ops::Range;
+use std::sync::Arc;
+
+/// Parses an attribute sequence, ignoring its diagnostics. The lexer already reports those with their positions.
+pub fn parse_attr(inp: &str) -> Attributes {
+	Attributes::parse(inp, 0).0
+}
+
+/// Settings that change how a document is parsed or rendered.
+#[derive(Clone)]
+pub struct Options {
+	/// Adds a `data-line` attribute with the source line to every block level HTML element.
+	pub source_lines: bool,
+	/// Filters raw HTML, attributes and URLs, for documents that can't be trusted. `None` lets everything through.
+	pub safe_mode: Option<SafeMode>,
+	/// Gives headers without an id one made from their text. Documents with a `[[toc]]` always get them, so the table of contents can link to its headers.
+	pub header_ids: bool,
+	/// Adds `loading="lazy"` to images that don't say how they should be loaded. On by default.
+	pub lazy_images: bool,
+	/// Changes the source of every image, or reports the ones that can't be used. `images::local_files` checks that they exist next to the document.
+	pub image_resolver: Option<Arc<ImageResolver>>,
+	/// Adds empty cells to the rows of a table that are shorter than the others.
+	pub pad_tables: bool,
+}
+
+impl Default for Options {
+	fn default() -> Self {
+		Self { source_lines: false, safe_mode: None, header_ids: false, lazy_images: true, image_resolver: None, pad_tables: false }
+	}
+}
+
+impl fmt::Debug for Options {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.debug_struct("Options")
+			.field("source_lines", &self.source_lines)
+			.field("safe_mode", &self.safe_mode)
+			.field("header_ids", &self.header_ids)
+			.field("lazy_images", &self.lazy_images)
+			.field("image_resolver", &self.image_resolver.as_ref().map(|_| "..."))
+			.field("pad_tables", &self.pad_tables)
+			.finish()
+	}
+}
+
+pub fn parse(input: &str) -> (String, Vec<Diagnostic>) {
+	parse_with(input, &Options::default())
+}
+
+pub fn parse_with(input: &str, options: &Options) -> (String, Vec<Diagnostic>) {
+	let (document, warnings) = parse_document_with(input, options);
+	(html::render_with(&document, options), warnings)
+}
+
+pub fn parse_document(input: &str) -> (Document, Vec<Diagnostic>) {
+	parse_document_with(input, &Options::default())
+}
+
+pub fn parse_document_with(input: &str, options: &Options) -> (Document, Vec<Diagnostic>) {
+	let (blocks, mut warnings) = block_lexer(input);
+	let builder = Builder::new(input);
+	let mut document = Document { blocks: builder.blocks(&blocks), footnotes: Vec::new() };
+	visit_inline_lists(&mut document.blocks, &mut autolink);
+	for note in builder.footnotes.borrow_mut().iter_mut() {
+		autolink(&mut note.content);
+		visit_inline_lists(&mut note.children, &mut autolink);
+	}
+	resolve_footnotes(&mut document, builder.footnotes.into_inner(), &mut warnings);
+	resolve_links(&mut document, builder.links.into_inner(), &mut warnings);
+	resolve_figures(&mut document);
+	check_tables(&mut document, options.pad_tables, &mut warnings);
+	if let Some(resolver) = &options.image_resolver {
+		resolve_images(&mut document, resolver.as_ref(), &mut warnings);
+	}
+	let mut has_toc = false;
+	visit_document_blocks(&mut document, &mut |x| has_toc |= matches!(x.kind, BlockKind::Toc { .. }));
+	if options.header_ids || has_toc {
+		toc::header_ids(&mut document);
+	}
+	if has_toc {
+		let contents = toc::toc(&document);
+		visit_document_blocks(&mut document, &mut |block| {
+			if let BlockKind::Toc { entries } = &mut block.kind {
+				*entries = contents.clone();
+			}
+		});
+	}
+	warnings.sort_by_key(|x| x.span.start);
+	for warning in warnings.iter_mut() {
+		warning.locate(input);
+	}
+	(document, warnings)
+}
+
+/// Turns the tokens of the lexers into document nodes.
+struct Builder {
+	/// Byte position where each line of the source starts.
+	line_starts: Vec<usize>,
+	/// Footnote definitions, which are taken out of wherever they were written.
+	footnotes: RefCell<Vec<Footnote>>,
+	/// Reference link definitions, which are also taken out of the document.
+	links: RefCell<Vec<LinkDefinition>>,
+}
+
+pub(crate) struct LinkDefinition {
+	pub(crate) label: String,
+	pub(crate) destination: String,
+	pub(crate) title: Option<String>,
+	pub(crate) attributes: Attributes,
+	pub(crate) span: Range<usize>,
+}
+
+/// A document as it was written, before anything in it is resolved, with its footnote and link definitions apart from its blocks.
+pub(crate) struct Source {
+	pub(crate) blocks: Vec<Block>,
+	pub(crate) footnotes: Vec<Footnote>,
+	pub(crate) links: Vec<LinkDefinition>,
+}
+
+/// Parses a document without resolving its footnotes, links, figures or tables and without turning bare URLs into links, so it can be written back as it was.
+pub(crate) fn parse_source(input: &str) -> Source {
+	let (blocks, _) = block_lexer(input);
+	let builder = Builder::new(input);
+	let blocks = builder.blocks(&blocks);
+	Source { blocks, footnotes: builder.footnotes.into_inner(), links: builder.links.into_inner() }
+}
+
+impl Builder {
+	fn new(input: &str) -> Self {
+		let mut line_starts = vec![0];
+		line_starts.extend(input.match_indices('\n').map(|(x, _)| x + 1));
+		Self { line_starts, footnotes: RefCell::new(Vec::new()), links: RefCell::new(Vec::new()) }
+	}
+
+	fn line(&self, pos: usize) -> usize {
+		self.line_starts.partition_point(|x| *x <= pos)
+	}
+
+	fn blocks(&self, blocks: &[Token]) -> Vec<Block> {
+		let mut out = Vec::new();
+		for block in blocks {
+			match block.class {
+				TokenType::ListBlock => out.append(&mut self.lists(&block.subtokens)),
+				TokenType::LinkDef => {
+					let definition = LinkDefinition {
+						label: block.content.to_owned(),
+						destination: block.subtokens.first().map(|x| x.content.to_owned()).unwrap_or_default(),
+						title: block.subtokens.get(1).map(|x| x.content.to_owned()),
+						attributes: parse_attr(&block.attributes),
+						span: block.span.clone(),
+					};
+					self.links.borrow_mut().push(definition);
+				},
+				TokenType::FootnoteDef => {
+					let item = self.list_item(block);
+					let note = Footnote { label: block.content.to_owned(), number: 0, references: 0, attributes: item.attributes, content: item.content, children: item.children, span: item.span, line: item.line };
+					self.footnotes.borrow_mut().push(note);
+				},
+				_ => out.push(self.block(block)),
+			}
+		}
+		out
+	}
+
+	fn block(&self, block: &Token) -> Block {
+		let kind = match block.class {
+			TokenType::Image => BlockKind::Image(parse_image(&block.content[1..block.content.len()-1])),
+			TokenType::Header => BlockKind::Header { level: block.content.len(), content: self.inlines(&block.subtokens) },
+			TokenType::Html => {
+				let tag = if block.content == "<>" { String::new() } else { block.content.to_owned() };
+				BlockKind::Html { tag, content: self.inlines(&block.subtokens) }
 			},
-			"class" => {
-				match cha {
-					' ' => {
-						class += &cha.to_string();
-						current_type = "none";
-					},
-					_ => class += &cha.to_string(),
-				}
+			TokenType::CodeBlock => BlockKind::Code { language: block.subtokens.first().map(|x| x.content.to_owned()), content: block.content.to_owned() },
+			TokenType::Quote => {
+				let (attribution, content) = match block.subtokens.split_last() {
+					Some((last, content)) if matches!(last.class, TokenType::Citation) => (Some(self.inlines(&last.subtokens)), content),
+					_ => (None, &block.subtokens[..]),
+				};
+				BlockKind::Quote { content: self.blocks(content), attribution }
 			},
-			"id" => {
-				match cha {
-					' ' => {
-						current_type = "none";
-					},
-					_ => id += &cha.to_string(),
-				}
+			TokenType::Toc => BlockKind::Toc { entries: Vec::new() },
+			TokenType::Figure => match &block.subtokens[..] {
+				[content, caption] => BlockKind::Figure { number: 0, content: Box::new(self.block(content)), caption: self.inlines(&caption.subtokens) },
+				_ => BlockKind::Paragraph(Vec::new()),
 			},
-			_ => panic!("Attribute parser reached undefined state"),
+			TokenType::DefList => BlockKind::DefinitionList { entries: self.definitions(block) },
+			TokenType::Table => self.table(block),
+			_ => BlockKind::Paragraph(self.inlines(&block.subtokens)),
+		};
+		Block { kind, attributes: parse_attr(&block.attributes), span: block.span.clone(), line: self.line(block.span.start) }
+	}
+
+	/// Splits the rows of a table into its head, body and foot. The rows before the column specification are the head, and so is a first row with only header cells. The rows after a `|===|` are the foot.
+	fn table(&self, table: &Token) -> BlockKind {
+		let mut caption = None;
+		let mut columns: Option<Vec<TableColumn>> = None;
+		let (mut head, mut body, mut foot) = (Vec::new(), Vec::new(), Vec::new());
+		let mut in_foot = false;
+		for token in table.subtokens.iter() {
+			match token.class {
+				TokenType::Caption if caption.is_none() => caption = Some(self.inlines(&token.subtokens)),
+				TokenType::TableColumns if columns.is_none() && !in_foot => {
+					columns = Some(token.subtokens.iter().map(|x| TableColumn { align: alignment(&x.content), attributes: parse_attr(&x.attributes) }).collect());
+					head.append(&mut body);
+				},
+				TokenType::TableFoot => in_foot = true,
+				TokenType::TableRow if in_foot => foot.push(self.table_row(token)),
+				TokenType::TableRow => body.push(self.table_row(token)),
+				_ => (),
+			}
+		}
+		if head.is_empty() && body.first().is_some_and(|x| !x.cells.is_empty() && x.cells.iter().all(|x| x.header)) {
+			head.push(body.remove(0));
+		}
+		for cell in head.iter_mut().flat_map(|x| x.cells.iter_mut()) {
+			cell.header = true;
+		}
+		BlockKind::Table { caption, columns: columns.unwrap_or_default(), head, body, foot }
+	}
+
+	fn table_row(&self, row: &Token) -> TableRow {
+		let cells = row.subtokens.iter().map(|cell| {
+			let (content, children) = self.body(&cell.subtokens);
+			TableCell {
+				header: matches!(cell.class, TokenType::TableHeader),
+				align: None,
+				attributes: parse_attr(&cell.attributes),
+				content: self.inlines(content),
+				children,
+				span: cell.span.clone(),
+				line: self.line(cell.span.start),
+			}
+		}).collect();
+		TableRow { attributes: parse_attr(&row.attributes), cells, span: row.span.clone(), line: self.line(row.span.start) }
+	}
+
+	/// Splits the subtokens of a list item or table cell into its inline tokens and the blocks of its `ItemBody`.
+	fn body<'a>(&self, subtokens: &'a [Token]) -> (&'a [Token], Vec<Block>) {
+		match subtokens.split_last() {
+			Some((last, content)) if matches!(last.class, TokenType::ItemBody) => (content, self.blocks(&last.subtokens)),
+			_ => (subtokens, Vec::new()),
+		}
+	}
+
+	fn list_item(&self, item: &Token) -> ListItem {
+		let (content, children) = self.body(&item.subtokens);
+		ListItem {
+			attributes: parse_attr(&item.attributes),
+			content: self.inlines(content),
+			children,
+			span: item.span.clone(),
+			line: self.line(item.span.start),
+		}
+	}
+
+	/// Groups the terms and definitions of a definition list into entries. A term after a definition starts a new entry.
+	fn definitions(&self, list: &Token) -> Vec<DefinitionEntry> {
+		let mut entries: Vec<DefinitionEntry> = Vec::new();
+		for item in list.subtokens.iter() {
+			let term = matches!(item.class, TokenType::DefTerm);
+			match entries.last_mut() {
+				Some(entry) if term && entry.definitions.is_empty() => entry.terms.push(self.list_item(item)),
+				Some(entry) if !term => entry.definitions.push(self.list_item(item)),
+				_ if term => entries.push(DefinitionEntry { terms: vec![self.list_item(item)], definitions: Vec::new() }),
+				_ => entries.push(DefinitionEntry { terms: Vec::new(), definitions: vec![self.list_item(item)] }),
+			}
+		}
+		entries
+	}
+
+	/// Turns the flat sequence of `UList`/`OList` tokens in a `ListBlock` into nested lists.
+	fn lists(&self, lists: &[Token]) -> Vec<Block> {
+		let mut roots: Vec<Block> = Vec::new();
+		let mut stack: Vec<(usize, Block)> = Vec::new();
+		for list in lists.iter() {
+			let depth = get_list_depth(list);
+			let ordered = matches!(list.class, TokenType::OList);
+			let mut items: Vec<ListItem> = list.subtokens.iter().map(|item| self.list_item(item)).collect();
+			while matches!(stack.last(), Some((d, _)) if *d > depth) {
+				close_list(&mut stack, &mut roots);
+			}
+			if let Some((d, last)) = stack.last_mut() {
+				if *d == depth {
+					match &mut last.kind {
+						BlockKind::List { ordered: o, items: last_items } if *o == ordered => {
+							last_items.append(&mut items);
+							last.span.end = list.span.end;
+							continue;
+						},
+						_ => close_list(&mut stack, &mut roots),
+					}
+				}
+			}
+			let block = Block { kind: BlockKind::List { ordered, items }, attributes: parse_attr(&list.attributes), span: list.span.clone(), line: self.line(list.span.start) };
+			stack.push((depth, block));
 		}
+		while !stack.is_empty() {
+			close_list(&mut stack, &mut roots);
+		}
+		roots
 	}
-	if id != "" {
-		out += &("id=\"".to_string() + &id + "\" ");
+
+	fn inlines(&self, input: &[Token]) -> Vec<Inline> {
+		let mut out = Vec::new();
+		let mut skip = false;
+		for (iter, i) in input.iter().enumerate() {
+			if skip {
+				skip = false;
+				continue;
+			}
+			let mut attributes = &i.attributes;
+			let mut merged = Attributes::default();
+			let mut span = i.span.clone();
+			let kind = match i.class {
+				TokenType::Put | TokenType::ListEl | TokenType::NumberedListEl => InlineKind::Text(i.content.to_owned()),
+				TokenType::Bold => InlineKind::Bold(self.inlines(&i.subtokens)),
+				TokenType::Italic => InlineKind::Italic(self.inlines(&i.subtokens)),
+				TokenType::Emphasis => InlineKind::Emphasis(self.inlines(&i.subtokens)),
+				TokenType::Strong => InlineKind::Strong(self.inlines(&i.subtokens)),
+				TokenType::Sub => InlineKind::Sub(self.inlines(&i.subtokens)),
+				TokenType::Sup => InlineKind::Sup(self.inlines(&i.subtokens)),
+				TokenType::Span => InlineKind::Span(self.inlines(&i.subtokens)),
+				TokenType::Strike => InlineKind::Strike(self.inlines(&i.subtokens)),
+				TokenType::Under => InlineKind::Under(self.inlines(&i.subtokens)),
+				TokenType::Html => InlineKind::Html(i.content.to_owned()),
+				TokenType::Code => InlineKind::Code(i.content[1..i.content.len()-1].to_owned()),
+				TokenType::LineBreak => InlineKind::LineBreak,
+				TokenType::Image => InlineKind::Image(parse_image(&i.content[1..i.content.len()-1])),
+				TokenType::LinkName => {
+					let content = self.inlines(&i.subtokens);
+					match input.get(iter + 1) {
+						Some(next) if matches!(next.class, TokenType::LinkDir) => {
+							// The attributes of the destination go after the ones of the name, so they win
+							merged = parse_attr(&i.attributes);
+							merged.merge(&parse_attr(&next.attributes));
+							span.end = next.span.end;
+							let (destination, title) = link_destination(&next.content[1..next.content.len()-1]);
+							InlineKind::Link { destination: Some(destination), title, content }
+						},
+						Some(next) if matches!(next.class, TokenType::LinkName) => {
+							// A reference link like [name][label], or [name][] to use the name as the label
+							attributes = &next.attributes;
+							span.end = next.span.end;
+							skip = true;
+							let label = &next.content[1..next.content.len()-1];
+							let label = if label.trim().is_empty() { plain_text(&content) } else { label.to_owned() };
+							InlineKind::LinkRef { label, content }
+						},
+						_ => match footnote_label(&i.content) {
+							Some(label) => InlineKind::FootnoteRef { label: label.to_owned(), number: None, reference: 0 },
+							_ => InlineKind::Link { destination: None, title: None, content },
+						},
+					}
+				},
+				TokenType::LinkDir => continue,
+				_ => InlineKind::Raw(i.content.to_owned()),
+			};
+			let line = self.line(span.start);
+			let attributes = if merged.is_empty() { parse_attr(attributes) } else { merged };
+			out.push(Inline { kind, attributes, span, line });
+		}
+		out
 	}
-	if class != "" {
-		out += &("class=\"".to_string() + &class + "\" ");
+}
+
+/// Reads the alignment of a column specification like `:-:`.
+fn alignment(spec: &str) -> Option<Alignment> {
+	match (spec.starts_with(':'), spec.ends_with(':')) {
+		(true, true) => Some(Alignment::Center),
+		(true, false) => Some(Alignment::Left),
+		(false, true) => Some(Alignment::Right),
+		(false, false) => None,
 	}
-	out + &everything_else
 }
 
-pub fn parse(input: &str) -> (String, String) {
-	let mut tokvec:Vec<Vec<Token>> = Vec::new();
-	let mut out = String::new();
-	let mut warnings = String::new();
-	for i in input.lines() {
-		let (tokens, warns) = tokenize(i);
-		tokvec.push(tokens);
-		warnings += &warns;	
+/// Splits the inside of a `(destination "title")` into the destination and the title. The title can also go between single quotes.
+fn link_destination(inner: &str) -> (String, Option<String>) {
+	let inner = inner.trim();
+	for quote in ['"', '\''] {
+		if let Some(stripped) = inner.strip_suffix(quote) {
+			if let Some(pos) = stripped.rfind(quote).filter(|x| stripped[..*x].ends_with(char::is_whitespace)) {
+				return (stripped[..pos].trim_end().to_owned(), Some(stripped[pos + 1..].to_owned()))
+			}
+		}
 	}
-	let blocks = block_lexer(&tokvec);
-	for block in blocks {
-		match block.class {
-			TokenType::Para => out += &("<p ".to_owned() + &parse_attr(&block.attributes) + ">" + &parse_line(&block.subtokens) + "</p>\n"),
-			TokenType::Image => out += &("<img ".to_owned() + &parse_attr(&block.attributes) + " src=\"" + &block.content[1..block.content.len()-1] + "\"/>\n"),
-			TokenType::Header => out += &("<h".to_owned() + &block.content.len().to_string() + " " + &parse_attr(&block.attributes) + ">" + &parse_line(&block.subtokens) + "</h" + &block.content.len().to_string() + ">\n"),
-			TokenType::Html => {
-				if block.content == "<>" {
-					out += &(parse_line(&block.subtokens) + "\n");
-				} else {
-					out += &(block.content.to_owned() + &parse_line(&block.subtokens) + "\n");
+	(inner.to_owned(), None)
+}
+
+fn close_list(stack: &mut Vec<(usize, Block)>, roots: &mut Vec<Block>) {
+	if let Some((_, list)) = stack.pop() {
+		match stack.last_mut() {
+			Some((_, Block { kind: BlockKind::List { items, .. }, span, .. })) if !items.is_empty() => {
+				span.end = span.end.max(list.span.end);
+				if let Some(item) = items.last_mut() {
+					item.span.end = item.span.end.max(list.span.end);
+					item.children.push(list);
 				}
 			},
-			TokenType::Table => {
-				out += &("<table ".to_owned() + &parse_attr(&block.attributes) + ">\n");
-				for row in block.subtokens {
-					out += &("<tr ".to_owned() + &parse_attr(&row.attributes) + ">\n");
-					for cell in row.subtokens {
-						let htag = match cell.class {
-							TokenType::TableCell => "td",
-							TokenType::TableHeader => "th",
-							_ => panic!("Non-table token when expecting table token"),
-						};
-						out += &("<".to_owned() + htag + " " + &parse_attr(&cell.attributes)+ ">");
-						out += &parse_line(&cell.subtokens);
-						out += &("</".to_owned() + htag + ">\n");
-					}
-					out += "</tr>\n";
+			_ => roots.push(list),
+		}
+	}
+}
+
+/// Numbers the footnotes in the order they're first referenced, and points every reference to its footnote. Footnotes that aren't referenced anywhere are left out of the document.
+fn resolve_footnotes(document: &mut Document, definitions: Vec<Footnote>, warnings: &mut Vec<Diagnostic>) {
+	let mut unused: Vec<Footnote> = Vec::new();
+	for note in definitions {
+		if unused.iter().any(|x| x.label == note.label) {
+			warnings.push(Diagnostic::warning("duplicate-footnote", format!("Footnote {} is defined more than once, only the first definition is used", note.label), note.span));
+		} else {
+			unused.push(note);
+		}
+	}
+	let mut used: Vec<Footnote> = Vec::new();
+	let mut resolve = |inline: &mut Inline, used: &mut Vec<Footnote>| {
+		let InlineKind::FootnoteRef { label, number, reference } = &mut inline.kind else { return };
+		if let Some(note) = used.iter_mut().find(|x| x.label == *label) {
+			note.references += 1;
+			*number = Some(note.number);
+			*reference = note.references;
+		} else if let Some(pos) = unused.iter().position(|x| x.label == *label) {
+			let mut note = unused.remove(pos);
+			note.number = used.len() + 1;
+			note.references = 1;
+			*number = Some(note.number);
+			*reference = 1;
+			used.push(note);
+		} else {
+			warnings.push(Diagnostic::warning("undefined-footnote", format!("Footnote {} isn't defined anywhere", label), inline.span.clone()));
+		}
+	};
+	visit_inlines(&mut document.blocks, &mut |x| resolve(x, &mut used));
+	// Footnotes can reference other footnotes, which get numbered after the ones in the document
+	let mut iter = 0;
+	while iter < used.len() {
+		let mut content = std::mem::take(&mut used[iter].content);
+		let mut children = std::mem::take(&mut used[iter].children);
+		visit_inline_list(&mut content, &mut |x| resolve(x, &mut used));
+		visit_inlines(&mut children, &mut |x| resolve(x, &mut used));
+		used[iter].content = content;
+		used[iter].children = children;
+		iter += 1;
+	}
+	for note in unused {
+		warnings.push(Diagnostic::warning("unused-footnote", format!("Footnote {} isn't referenced anywhere, so it was left out", note.label), note.span));
+	}
+	document.footnotes = used;
+}
+
+/// Turns reference links into links to the destination of their definition. Labels are matched ignoring case and spacing.
+fn resolve_links(document: &mut Document, definitions: Vec<LinkDefinition>, warnings: &mut Vec<Diagnostic>) {
+	let normalize = |x: &str| x.split_whitespace().collect::<Vec<&str>>().join(" ").to_lowercase();
+	let mut defined: Vec<(String, LinkDefinition)> = Vec::new();
+	for definition in definitions {
+		let label = normalize(&definition.label);
+		if defined.iter().any(|(x, _)| *x == label) {
+			warnings.push(Diagnostic::warning("duplicate-link-definition", format!("Link {} is defined more than once, only the first definition is used", definition.label), definition.span));
+		} else {
+			defined.push((label, definition));
+		}
+	}
+	let mut resolve = |inline: &mut Inline| {
+		let InlineKind::LinkRef { label, content } = &mut inline.kind else { return };
+		match defined.iter().find(|(x, _)| *x == normalize(label)) {
+			Some((_, definition)) => {
+				let mut attributes = definition.attributes.clone();
+				attributes.merge(&inline.attributes);
+				inline.attributes = attributes;
+				inline.kind = InlineKind::Link { destination: Some(definition.destination.to_owned()), title: definition.title.clone(), content: std::mem::take(content) };
+			},
+			None => warnings.push(Diagnostic::warning("undefined-link", format!("Link {} isn't defined anywhere", label), inline.span.clone())),
+		}
+	};
+	visit_inlines(&mut document.blocks, &mut resolve);
+	for note in document.footnotes.iter_mut() {
+		visit_inline_list(&mut note.content, &mut resolve);
+		visit_inlines(&mut note.children, &mut resolve);
+	}
+}
+
+/// Numbers the figures of the document and gives the ones without an id `fig-N`. Links to a figure without any text of their own, like `[](#fig-2)`, get "Figure 2" as their text.
+fn resolve_figures(document: &mut Document) {
+	let mut figures: Vec<(String, usize)> = Vec::new();
+	visit_blocks(&mut document.blocks, &mut |block| {
+		let BlockKind::Figure { number, .. } = &mut block.kind else { return };
+		*number = figures.len() + 1;
+		let id = block.attributes.id.get_or_insert_with(|| format!("fig-{}", number));
+		figures.push((id.to_owned(), *number));
+	});
+	if figures.is_empty() {
+		return
+	}
+	let mut resolve = |inline: &mut Inline| {
+		let InlineKind::Link { destination: Some(destination), content, .. } = &mut inline.kind else { return };
+		let Some((_, number)) = figures.iter().find(|(id, _)| destination.strip_prefix('#') == Some(id.as_str())) else { return };
+		if content.is_empty() {
+			content.push(Inline { kind: InlineKind::Text(format!("Figure {}", number)), attributes: Attributes::default(), span: inline.span.clone(), line: inline.line });
+		}
+	};
+	visit_inlines(&mut document.blocks, &mut resolve);
+	for note in document.footnotes.iter_mut() {
+		visit_inline_list(&mut note.content, &mut resolve);
+		visit_inlines(&mut note.children, &mut resolve);
+	}
+}
+
+/// Runs the source of every image through the resolver of the options, warning about the ones it rejects.
+fn resolve_images(document: &mut Document, resolver: &ImageResolver, warnings: &mut Vec<Diagnostic>) {
+	let mut resolve = |image: &mut Image, span: &Range<usize>| {
+		let sources = std::iter::once(&mut image.source).chain(image.srcset.iter_mut().map(|(x, _)| x));
+		for source in sources {
+			match resolver(source) {
+				Ok(x) => *source = x,
+				Err(x) => warnings.push(Diagnostic::warning("unresolved-image", x, span.clone())),
+			}
+		}
+	};
+	visit_blocks(&mut document.blocks, &mut |block| {
+		if let BlockKind::Image(image) = &mut block.kind {
+			resolve(image, &block.span);
+		}
+	});
+	let mut inline = |x: &mut Inline| {
+		if let InlineKind::Image(image) = &mut x.kind {
+			resolve(image, &x.span);
+		}
+	};
+	visit_inlines(&mut document.blocks, &mut inline);
+	for note in document.footnotes.iter_mut() {
+		visit_inline_list(&mut note.content, &mut inline);
+		visit_inlines(&mut note.children, &mut inline);
+	}
+}
+
+/// Calls `f` on every block in `blocks` and the blocks inside them, in the order they appear in the document. Each block is visited before its children.
+pub(crate) fn visit_blocks(blocks: &mut [Block], f: &mut dyn FnMut(&mut Block)) {
+	for block in blocks.iter_mut() {
+		f(block);
+		match &mut block.kind {
+			BlockKind::Quote { content, .. } => visit_blocks(content, f),
+			BlockKind::Figure { content, .. } => visit_blocks(std::slice::from_mut(&mut **content), f),
+			BlockKind::Table { head, body, foot, .. } => {
+				for cell in head.iter_mut().chain(body.iter_mut()).chain(foot.iter_mut()).flat_map(|x| x.cells.iter_mut()) {
+					visit_blocks(&mut cell.children, f);
 				}
-				out += "</table>\n";
 			},
-			TokenType::ListBlock => {
-				let mut list_types: Vec<&str> = Vec::new();
-				let mut last_level = 0;
-				for i in block.subtokens.iter() {
-					match i.class {
-						TokenType::UList => {
-							match list_types.last(){
-								None => {
-									out += &("<ul ".to_owned() + &parse_attr(&i.attributes) + ">\n");
-									last_level = 1;
-									list_types.push("ul");
-									out += &parse_line(&i.subtokens);
-								},
-								Some(x) => {
-									let last_depth = get_list_depth(&i);
-									if x == &"ul" {
-										if last_depth > last_level {
-											out += &("<ul ".to_owned() + &parse_attr(&i.attributes) + ">\n");
-											list_types.push("ul");
-										} else if last_depth < last_level {
-											for _ in last_depth..last_level {
-												out += &("</".to_string() + list_types.pop().unwrap() + ">\n"); // This should close the latest opened list type
-											}
-										}
-										last_level = last_depth;
-										out += &parse_line(&i.subtokens);
-									} else {
-										if last_depth < last_level {
-											for _ in last_depth..last_level {
-												out += &("</".to_string() + list_types.pop().unwrap() + ">\n"); // This should close the latest opened list type
-											}
-											if let Some(x) = list_types.last() {
-												if x == &"ol" {
-													out += &("</".to_string() + list_types.pop().unwrap() + ">\n"); // This should close the latest opened list type
-													last_level -= 1;
-												}
-											}
-										}
-										out += &("<ul ".to_owned() + &parse_attr(&i.attributes) + ">\n");
-										last_level += 1;
-										list_types.push("ul");
-										out += &parse_line(&i.subtokens);
-									}
-								}
-							}
-						},
-						TokenType::OList => {
-							match list_types.last(){
-								None => {
-									out += &("<ol ".to_owned() + &parse_attr(&i.attributes) + ">\n");
-									last_level = 1;
-									list_types.push("ol");
-									out += &parse_line(&i.subtokens);
-								},
-								Some(x) => {
-									let last_depth = get_list_depth(&i);
-									if x == &"ol" {
-										if last_depth > last_level {
-											out += &("<ol ".to_owned() + &parse_attr(&i.attributes) + ">\n");
-											list_types.push("ol");
-										} else if last_depth < last_level {
-											for _ in last_depth..last_level {
-												out += &("</".to_string() + list_types.pop().unwrap() + ">\n"); // This should close the latest opened list type
-											}
-										}
-										last_level = last_depth;
-										out += &parse_line(&i.subtokens);
-									} else {
-										if last_depth <= last_level {
-											for _ in last_depth..last_level {
-												out += &("</".to_string() + list_types.pop().unwrap() + ">\n"); // This should close the latest opened list type
-											}
-											if let Some(x) = list_types.last() {
-												if x == &"ul" {
-													out += &("</".to_string() + list_types.pop().unwrap() + ">\n"); // This should close the latest opened list type
-													last_level -= 1;
-												}
-											}
-										}
-										out += &("<ol ".to_owned() + &parse_attr(&i.attributes) + ">\n");
-										last_level += 1;
-										list_types.push("ol");
-										out += &parse_line(&i.subtokens);
-									}
-								}
-							}
-						}
-						_ => ()
-					}
+			BlockKind::List { items, .. } => {
+				for item in items.iter_mut() {
+					visit_blocks(&mut item.children, f);
 				}
-				for _ in 0..last_level {
-					match list_types.pop() {
-						None => (),
-						Some(x) => out += &("</".to_string() + x + ">\n"),
-					}// This should close the latest opened list type
+			},
+			BlockKind::DefinitionList { entries } => {
+				for item in entries.iter_mut().flat_map(|x| x.terms.iter_mut().chain(x.definitions.iter_mut())) {
+					visit_blocks(&mut item.children, f);
 				}
-			}
+			},
 			_ => (),
 		}
 	}
-	(out, warnings)
-}
-
-
-fn parse_line(input: &Vec<Token>) -> String {
-	let mut out = String::new();
-	let mut iter: usize = 0;
-	for i in input.iter() {
-		match i.class {
-			TokenType::Put => out += &encode(&i.content, EntitySet::SpecialCharsAndNoASCII, EncodeType::NamedOrHex).iter().collect::<String>(),
-			TokenType::Bold => out += &("<b ".to_owned() + &parse_attr(&i.attributes) + ">" + &parse_line(&i.subtokens) + "</b>"),
-			TokenType::Italic => out += &("<i ".to_owned() + &parse_attr(&i.attributes) + ">" + &parse_line(&i.subtokens) + "</i>"),
-			TokenType::Emphasis => out += &("<em ".to_owned() + &parse_attr(&i.attributes) + ">" + &parse_line(&i.subtokens) + "</em>"),
-			TokenType::Strong => out += &("<strong ".to_owned() + &parse_attr(&i.attributes) + ">" + &parse_line(&i.subtokens) + "</strong>"),
-			TokenType::Sub => out += &("<sub ".to_owned() + &parse_attr(&i.attributes) + ">" + &parse_line(&i.subtokens) + "</sub>"),
-			TokenType::Sup => out += &("<sup ".to_owned() + &parse_attr(&i.attributes) + ">" + &parse_line(&i.subtokens) + "</sup>"),
-			TokenType::Span => out += &("<span ".to_owned() + &parse_attr(&i.attributes) + ">" + &parse_line(&i.subtokens) + "</span>"),
-			TokenType::Strike => out += &("<del ".to_owned() + &parse_attr(&i.attributes) + ">" + &parse_line(&i.subtokens) + "</del>"),
-			TokenType::Under => out += &("<u ".to_owned() + &parse_attr(&i.attributes) + ">" + &parse_line(&i.subtokens) + "</u>"),
-			TokenType::Html => out += &i.content,
-			TokenType::Code => out += &("<code ".to_owned() + &parse_attr(&i.attributes) + ">" + &encode(&i.content[1..i.content.len()-1], EntitySet::SpecialCharsAndNoASCII, EncodeType::NamedOrHex).iter().collect::<String>() + "</code>"),
-			TokenType::LineBreak => out += "</br>",
-			TokenType::Image => out += &("<img ".to_owned() + &parse_attr(&i.attributes) + " src=\""+ &i.content[1..i.content.len()-1] + "\"/>"),
-			TokenType::LinkName => {
-				let parsed_name = parse_line(&i.subtokens);
-				if iter < input.len() - 1 {
-					let next = &input[iter + 1];
-					match next.class {
-						TokenType::LinkDir => out += &("<a href=\"".to_owned() + &next.content[1..next.content.len()-1] + "\" " + &parse_attr(&next.attributes) + ">" + &parsed_name + "</a>"),
-						_ => out += &("<a href=\"".to_owned() + &parsed_name + "\" "  + &parse_attr(&i.attributes) + ">" + &parsed_name + "</a>"),
-					}
-				} else { out += &("<a href=\"".to_owned() + &parsed_name + "\" "  + &parse_attr(&i.attributes) + ">" + &parsed_name + "</a>"); }
+}
+
+/// Calls `f` on every block of the document like `visit_blocks`, followed by the blocks of its footnotes.
+pub(crate) fn visit_document_blocks(document: &mut Document, f: &mut dyn FnMut(&mut Block)) {
+	visit_blocks(&mut document.blocks, f);
+	for note in document.footnotes.iter_mut() {
+		visit_blocks(&mut note.children, f);
+	}
+}
+
+/// Calls `f` on every inline in `blocks`, including the ones nested in other inlines, in the order they appear in the document.
+pub(crate) fn visit_inlines(blocks: &mut [Block], f: &mut dyn FnMut(&mut Inline)) {
+	visit_inline_lists(blocks, &mut |x| visit_inline_list(x, f));
+}
+
+/// Calls `f` on the content of every block in `blocks`, and of the blocks inside them, but not on the inlines nested in other inlines.
+pub(crate) fn visit_inline_lists(blocks: &mut [Block], f: &mut dyn FnMut(&mut Vec<Inline>)) {
+	for block in blocks.iter_mut() {
+		match &mut block.kind {
+			BlockKind::Paragraph(content) | BlockKind::Header { content, .. } | BlockKind::Html { content, .. } => f(content),
+			BlockKind::Image(_) | BlockKind::Code { .. } | BlockKind::Toc { .. } => (),
+			BlockKind::Quote { content, attribution } => {
+				visit_inline_lists(content, f);
+				if let Some(attribution) = attribution {
+					f(attribution);
+				}
+			},
+			BlockKind::Table { caption, head, body, foot, .. } => {
+				if let Some(caption) = caption {
+					f(caption);
+				}
+				for cell in head.iter_mut().chain(body.iter_mut()).chain(foot.iter_mut()).flat_map(|x| x.cells.iter_mut()) {
+					f(&mut cell.content);
+					visit_inline_lists(&mut cell.children, f);
+				}
+			},
+			BlockKind::Figure { content, caption, .. } => {
+				visit_inline_lists(std::slice::from_mut(&mut **content), f);
+				f(caption);
+			},
+			BlockKind::List { items, .. } => visit_items(items, f),
+			BlockKind::DefinitionList { entries } => {
+				for entry in entries.iter_mut() {
+					visit_items(&mut entry.terms, f);
+					visit_items(&mut entry.definitions, f);
+				}
 			},
-			TokenType::ListEl | TokenType::NumberedListEl => out += &("<li ".to_owned() + &parse_attr(&i.attributes) + ">" + &parse_line(&i.subtokens) + "</li>\n"),
-			TokenType::LinkDir => (),
-			_ => out += &i.content,
 		}
-		iter += 1;
 	}
-	out
+}
+
+fn visit_items(items: &mut [ListItem], f: &mut dyn FnMut(&mut Vec<Inline>)) {
+	for item in items.iter_mut() {
+		f(&mut item.content);
+		visit_inline_lists(&mut item.children, f);
+	}
+}
+
+fn visit_inline_list(inlines: &mut [Inline], f: &mut dyn FnMut(&mut Inline)) {
+	for inline in inlines.iter_mut() {
+		f(inline);
+		if let Some(children) = inline.kind.children_mut() {
+			visit_inline_list(children, f);
+		}
+	}
 }
@@ -0,0 +1,107 @@
+use crate::ast::{Block, BlockKind, Document, TableCell, TableColumn, TableRow};
+use crate::diagnostic::Diagnostic;
+use crate::syntax::visit_blocks;
+use std::ops::Range;
+
+/// Where the cells of a row start, and which columns the row takes up, counting the ones taken by cells with a rowspan in the rows above.
+pub(crate) struct RowLayout {
+	pub(crate) starts: Vec<usize>,
+	pub(crate) taken: Vec<bool>,
+}
+
+impl RowLayout {
+	/// How many columns the row takes up, up to the last one with a cell in it.
+	fn width(&self) -> usize {
+		self.taken.iter().rposition(|x| *x).map_or(0, |x| x + 1)
+	}
+}
+
+/// Checks that every row of every table is as wide as the table, and gives the cells the alignment of their column. If `pad` is set, rows that are too short get empty cells at the end.
+pub(crate) fn check_tables(document: &mut Document, pad: bool, warnings: &mut Vec<Diagnostic>) {
+	let mut check = |blocks: &mut [Block]| visit_blocks(blocks, &mut |block| {
+		let BlockKind::Table { columns, head, body, foot, .. } = &mut block.kind else { return };
+		// Rowspans don't go past the end of the head, body or foot they start in
+		let layouts: Vec<Vec<RowLayout>> = [&*head, &*body, &*foot].into_iter().map(|rows| layout(rows, warnings)).collect();
+		let width = match layouts.iter().flatten().next() {
+			_ if !columns.is_empty() => columns.len(),
+			Some(first) => first.width(),
+			None => return,
+		};
+		for (header, (rows, layouts)) in [true, false, false].into_iter().zip([head, body, foot].into_iter().zip(layouts)) {
+			for (row, layout) in rows.iter_mut().zip(layouts) {
+				check_row(row, &layout, width, header, pad, columns, warnings);
+			}
+		}
+	});
+	check(&mut document.blocks);
+	for note in document.footnotes.iter_mut() {
+		check(&mut note.children);
+	}
+}
+
+fn check_row(row: &mut TableRow, layout: &RowLayout, width: usize, header: bool, pad: bool, columns: &[TableColumn], warnings: &mut Vec<Diagnostic>) {
+	for (cell, start) in row.cells.iter_mut().zip(layout.starts.iter()) {
+		cell.align = columns.get(*start).and_then(|x| x.align);
+	}
+	if layout.width() > width {
+		warnings.push(Diagnostic::warning("table-row-too-long", format!("Row takes up {} columns, but the table only has {}", layout.width(), width), row.span.clone()));
+	}
+	let missing: Vec<usize> = (0..width).filter(|x| !layout.taken.get(*x).copied().unwrap_or(false)).collect();
+	if missing.is_empty() {
+		return
+	}
+	let message = if pad { "so empty cells were added" } else { "so the table won't be rectangular" };
+	warnings.push(Diagnostic::warning("table-row-too-short", format!("Row is missing {} of the table's {} columns, {}", missing.len(), width, message), row.span.clone()));
+	if pad {
+		for column in missing {
+			let align = columns.get(column).and_then(|x| x.align);
+			row.cells.push(TableCell { header, align, attributes: Default::default(), content: Vec::new(), children: Vec::new(), span: row.span.end..row.span.end, line: row.line });
+		}
+	}
+}
+
+/// Lays the rows out like browsers do: every cell starts at the first column that isn't taken yet, either by the cells before it or by a cell with a rowspan in the rows above. Cells that take a column that's already taken, and rowspans that go past the last row, are reported.
+pub(crate) fn layout(rows: &[TableRow], warnings: &mut Vec<Diagnostic>) -> Vec<RowLayout> {
+	// For each column, how many more rows it's taken for, and the cell that takes it
+	let mut spans: Vec<(usize, Range<usize>)> = Vec::new();
+	let mut out = Vec::new();
+	for row in rows.iter() {
+		let mut taken: Vec<bool> = spans.iter().map(|(x, _)| *x > 0).collect();
+		let mut starts = Vec::new();
+		let mut column = 0;
+		for cell in row.cells.iter() {
+			while taken.get(column).copied().unwrap_or(false) {
+				column += 1;
+			}
+			starts.push(column);
+			let span = |key: &str, max: usize| cell.attributes.get(key).and_then(|x| x.parse::<usize>().ok()).unwrap_or(1).clamp(1, max);
+			// Browsers don't let cells span more than 1000 columns or 65534 rows
+			let (colspan, rowspan) = (span("colspan", 1000), span("rowspan", 65534));
+			let end = column + colspan;
+			if taken.len() < end {
+				taken.resize(end, false);
+				spans.resize(end, (0, 0..0));
+			}
+			if taken[column..end].iter().any(|x| *x) {
+				warnings.push(Diagnostic::warning("table-cell-overlap", "Cell spans over a column that's already taken by a cell with a rowspan".to_owned(), cell.span.clone()));
+			}
+			for x in column..end {
+				taken[x] = true;
+				spans[x] = (rowspan, cell.span.clone());
+			}
+			column = end;
+		}
+		for (x, _) in spans.iter_mut() {
+			*x = x.saturating_sub(1);
+		}
+		out.push(RowLayout { starts, taken });
+	}
+	let mut reported: Vec<Range<usize>> = Vec::new();
+	for (_, span) in spans.into_iter().filter(|(x, _)| *x > 0) {
+		if !reported.contains(&span) {
+			warnings.push(Diagnostic::warning("table-rowspan-past-end", "Cell's rowspan goes past the last row it can reach, so it was cut short".to_owned(), span.clone()));
+			reported.push(span);
+		}
+	}
+	out
+}
@@ -0,0 +1,402 @@
+use crate::ast::{Block, BlockKind, DefinitionEntry, Document, Footnote, Image, Inline, InlineKind, ListItem, TableColumn, TableRow, TocEntry};
+use crate::render::Renderer;
+use crate::syntax::Options;
+use crate::tables::layout;
+use htmlentity::entity::decode;
+
+/// Renders a document as plain text with the default options, without wrapping its lines.
+pub fn render(document: &Document) -> String {
+	render_with(document, &Options::default(), None)
+}
+
+/// Renders a document as plain text, without any markup. If `width` is set, lines of text are wrapped so they aren't longer than that many characters, except for code blocks, tables, and words that are longer on their own.
+pub fn render_with(document: &Document, options: &Options, width: Option<usize>) -> String {
+	TextRenderer::new(options, width).document(document)
+}
+
+/// The start of a document's text, for previews: at most `length` characters of it, with all of its whitespace and line breaks turned into single spaces. Text that had to be cut is cut after a word if it can be, and ends with `…`.
+pub fn summary(document: &Document, length: usize) -> String {
+	let text = render(document).split_whitespace().collect::<Vec<_>>().join(" ");
+	if text.chars().count() <= length {
+		return text
+	}
+	// Leave room for the …
+	let Some(length) = length.checked_sub(1) else { return String::new() };
+	let cut: String = text.chars().take(length + 1).collect();
+	let cut = match cut.rfind(' ') {
+		Some(pos) => &cut[..pos],
+		None => cut.char_indices().nth(length).map_or(&cut[..], |(pos, _)| &cut[..pos]),
+	};
+	cut.trim_end().to_owned() + "…"
+}
+
+/// Renders documents as plain text. Lists keep their bullets and numbers, tables are written with their columns lined up, and attributes, raw HTML tags and the destinations of links are left out.
+pub struct TextRenderer<'a> {
+	options: &'a Options,
+	width: Option<usize>,
+	/// How far in the text being rendered is going to be indented, which wrapped lines leave room for.
+	indent: usize,
+}
+
+impl<'a> TextRenderer<'a> {
+	pub fn new(options: &'a Options, width: Option<usize>) -> Self {
+		Self { options, width, indent: 0 }
+	}
+
+	/// Wraps a line of text if there's a width to wrap it at.
+	fn wrap(&self, text: &str) -> String {
+		match self.width {
+			Some(width) => wrap(text, width.saturating_sub(self.indent).max(1)),
+			None => text.to_owned(),
+		}
+	}
+
+	fn wrapped(&mut self, content: &[Inline]) -> String {
+		let text = self.inlines(content);
+		self.wrap(&text)
+	}
+
+	/// Renders something that's going to be indented by `width` more characters.
+	fn indented(&mut self, width: usize, render: impl FnOnce(&mut Self) -> String) -> String {
+		self.indent += width;
+		let out = render(self);
+		self.indent -= width;
+		out
+	}
+
+	/// Renders a list item after its marker, with the lines after the first one lined up with its text.
+	fn item(&mut self, marker: &str, item: &ListItem) -> String {
+		let width = marker.chars().count();
+		let out = self.indented(width, |r| {
+			let mut out = r.wrapped(&item.content) + "\n";
+			match item.children.first() {
+				None => (),
+				// Lists nested right under the item's text don't need a blank line before them
+				Some(Block { kind: BlockKind::List { .. }, .. }) => out += &r.blocks(&item.children),
+				Some(_) => out += &("\n".to_owned() + &r.blocks(&item.children)),
+			}
+			out
+		});
+		marker.to_owned() + &indent(&out, width)
+	}
+
+	/// Renders the rows of a table with their columns lined up. Cells that span several columns take up their width and the separators between them.
+	fn rows(&mut self, columns: &[TableColumn], sections: [&[TableRow]; 3]) -> String {
+		// Every cell as the column it starts at, how many columns it takes and its text
+		let mut rows: Vec<Vec<(usize, usize, String)>> = Vec::new();
+		let mut ends = Vec::new();
+		for rows_of in sections.iter() {
+			let layouts = layout(rows_of, &mut Vec::new());
+			for (row, layout) in rows_of.iter().zip(layouts) {
+				let mut cells = Vec::new();
+				for (cell, start) in row.cells.iter().zip(layout.starts) {
+					let span = cell.attributes.get("colspan").and_then(|x| x.parse::<usize>().ok()).unwrap_or(1).clamp(1, 1000);
+					let mut text = self.inlines(&cell.content);
+					if !cell.children.is_empty() {
+						text += &(" ".to_owned() + &self.blocks(&cell.children));
+					}
+					cells.push((start, span, text.split_whitespace().collect::<Vec<_>>().join(" ")));
+				}
+				rows.push(cells);
+			}
+			ends.push(rows.len());
+		}
+		let count = rows.iter().flatten().map(|(start, span, _)| start + span).max().unwrap_or(0).max(columns.len());
+		let mut widths = vec![0; count];
+		for (start, _, text) in rows.iter().flatten().filter(|x| x.1 == 1) {
+			widths[*start] = widths[*start].max(text.chars().count());
+		}
+		for (start, span, text) in rows.iter().flatten().filter(|x| x.1 > 1) {
+			let room = widths[*start..start + span].iter().sum::<usize>() + 3 * (span - 1);
+			widths[start + span - 1] += text.chars().count().saturating_sub(room);
+		}
+		let rule = widths.iter().map(|x| "-".repeat(*x)).collect::<Vec<_>>().join("-+-") + "\n";
+		let mut out = String::new();
+		for (iter, cells) in rows.iter().enumerate() {
+			// A rule under the head and above the foot
+			if iter > 0 && ends[..2].contains(&iter) {
+				out += &rule;
+			}
+			let mut line = String::new();
+			let mut column = 0;
+			let mut cells = cells.iter().peekable();
+			while column < count {
+				if column > 0 {
+					line += " | ";
+				}
+				match cells.next_if(|x| x.0 == column) {
+					Some((start, span, text)) => {
+						let room = widths[*start..start + span].iter().sum::<usize>() + 3 * (span - 1);
+						line += &format!("{:<1$}", text, room);
+						column += span;
+					},
+					// Taken by a cell with a rowspan above, or missing from a short row
+					None => {
+						line += &" ".repeat(widths[column]);
+						column += 1;
+					},
+				}
+			}
+			out += &(line.trim_end().to_owned() + "\n");
+		}
+		out
+	}
+}
+
+impl Renderer for TextRenderer<'_> {
+	fn options(&self) -> &Options {
+		self.options
+	}
+
+	fn document(&mut self, document: &Document) -> String {
+		let blocks = self.blocks(&document.blocks);
+		let notes = self.footnotes(&document.footnotes);
+		if notes.is_empty() || blocks.is_empty() { blocks + &notes } else { blocks + "\n" + &notes }
+	}
+
+	/// Separates the blocks with blank lines, leaving out the ones without any text.
+	fn blocks(&mut self, blocks: &[Block]) -> String {
+		let mut out: Vec<String> = Vec::new();
+		for block in blocks.iter() {
+			let text = self.block(block);
+			if !text.trim().is_empty() {
+				out.push(text);
+			}
+		}
+		out.join("\n")
+	}
+
+	fn footnotes(&mut self, notes: &[Footnote]) -> String {
+		let mut out = String::new();
+		for note in notes.iter() {
+			let item = ListItem { attributes: note.attributes.clone(), content: note.content.clone(), children: note.children.clone(), span: note.span.clone(), line: note.line };
+			out += &self.item(&format!("[{}] ", note.number), &item);
+		}
+		out
+	}
+
+	fn paragraph(&mut self, _block: &Block, content: &[Inline]) -> String {
+		self.wrapped(content) + "\n"
+	}
+
+	fn header(&mut self, _block: &Block, _level: usize, content: &[Inline]) -> String {
+		self.wrapped(content) + "\n"
+	}
+
+	fn image_block(&mut self, block: &Block, _image: &Image) -> String {
+		self.wrap(block.attributes.get("alt").unwrap_or_default()) + "\n"
+	}
+
+	fn html_block(&mut self, _block: &Block, _tag: &str, content: &[Inline]) -> String {
+		self.wrapped(content) + "\n"
+	}
+
+	fn code_block(&mut self, _block: &Block, _language: Option<&str>, content: &str) -> String {
+		if content.ends_with('\n') { content.to_owned() } else { content.to_owned() + "\n" }
+	}
+
+	fn quote(&mut self, _block: &Block, content: &[Block], attribution: Option<&[Inline]>) -> String {
+		let inner = self.indented(2, |r| {
+			let mut inner = r.blocks(content);
+			if let Some(attribution) = attribution {
+				let attribution = "— ".to_owned() + &r.inlines(attribution);
+				inner += &("\n".to_owned() + &r.wrap(&attribution) + "\n");
+			}
+			inner
+		});
+		let mut out = String::new();
+		for line in inner.lines() {
+			out += &(if line.is_empty() { ">".to_owned() } else { "> ".to_owned() + line } + "\n");
+		}
+		out
+	}
+
+	fn table(&mut self, _block: &Block, caption: Option<&[Inline]>, columns: &[TableColumn], head: &[TableRow], body: &[TableRow], foot: &[TableRow]) -> String {
+		let caption = caption.map(|x| self.wrapped(x) + "\n").unwrap_or_default();
+		caption + &self.rows(columns, [head, body, foot])
+	}
+
+	fn figure(&mut self, _block: &Block, number: usize, content: &Block, caption: &[Inline]) -> String {
+		let label = if caption.is_empty() { format!("Figure {}", number) } else { format!("Figure {}: ", number) };
+		let caption = label + &self.inlines(caption);
+		self.block(content) + "\n" + &self.wrap(&caption) + "\n"
+	}
+
+	/// Writes every term on a line of its own, with their definitions indented under them.
+	fn definition_list(&mut self, _block: &Block, entries: &[DefinitionEntry]) -> String {
+		let mut out = String::new();
+		for entry in entries.iter() {
+			for term in entry.terms.iter() {
+				out += &self.item("", term);
+			}
+			for definition in entry.definitions.iter() {
+				out += &self.item("    ", definition);
+			}
+		}
+		out
+	}
+
+	fn toc(&mut self, _block: &Block, entries: &[TocEntry]) -> String {
+		toc_entries(entries, 0)
+	}
+
+	fn list(&mut self, _block: &Block, ordered: bool, items: &[ListItem]) -> String {
+		let mut out = String::new();
+		for (iter, item) in items.iter().enumerate() {
+			let marker = if ordered { format!("{}. ", iter + 1) } else { "* ".to_owned() };
+			out += &self.item(&marker, item);
+		}
+		out
+	}
+
+	fn inlines(&mut self, inlines: &[Inline]) -> String {
+		let mut out = String::new();
+		let mut after_break = false;
+		for inline in inlines.iter() {
+			let text = self.inline(inline);
+			// The spaces after a line break would indent the line it starts
+			out += if after_break { text.trim_start() } else { &text };
+			after_break = matches!(inline.kind, InlineKind::LineBreak);
+		}
+		out
+	}
+
+	/// The lines that continue a paragraph start with a line break, which is already there.
+	fn text(&mut self, _inline: &Inline, text: &str) -> String {
+		text.strip_prefix('\n').unwrap_or(text).to_owned()
+	}
+
+	/// Raw HTML loses its tags, and its entities become the characters they stand for.
+	fn raw(&mut self, _inline: &Inline, text: &str) -> String {
+		decode(&strip_tags(text)).into_iter().collect()
+	}
+
+	fn html(&mut self, _inline: &Inline, _text: &str) -> String {
+		String::new()
+	}
+
+	fn code(&mut self, _inline: &Inline, text: &str) -> String {
+		text.to_owned()
+	}
+
+	fn line_break(&mut self, _inline: &Inline) -> String {
+		"\n".to_owned()
+	}
+
+	fn image(&mut self, inline: &Inline, _image: &Image) -> String {
+		inline.attributes.get("alt").unwrap_or_default().to_owned()
+	}
+
+	fn link(&mut self, _inline: &Inline, _destination: Option<&str>, _title: Option<&str>, content: &[Inline]) -> String {
+		self.inlines(content)
+	}
+
+	fn link_ref(&mut self, _inline: &Inline, label: &str, content: &[Inline]) -> String {
+		"[".to_owned() + &self.inlines(content) + "][" + label + "]"
+	}
+
+	fn footnote_ref(&mut self, _inline: &Inline, label: &str, number: Option<usize>, _reference: usize) -> String {
+		match number {
+			Some(number) => format!("[{}]", number),
+			None => format!("[^{}]", label),
+		}
+	}
+
+	fn bold(&mut self, _inline: &Inline, content: &[Inline]) -> String {
+		self.inlines(content)
+	}
+
+	fn strong(&mut self, _inline: &Inline, content: &[Inline]) -> String {
+		self.inlines(content)
+	}
+
+	fn italic(&mut self, _inline: &Inline, content: &[Inline]) -> String {
+		self.inlines(content)
+	}
+
+	fn emphasis(&mut self, _inline: &Inline, content: &[Inline]) -> String {
+		self.inlines(content)
+	}
+
+	fn sub(&mut self, _inline: &Inline, content: &[Inline]) -> String {
+		self.inlines(content)
+	}
+
+	fn sup(&mut self, _inline: &Inline, content: &[Inline]) -> String {
+		self.inlines(content)
+	}
+
+	fn span(&mut self, _inline: &Inline, content: &[Inline]) -> String {
+		self.inlines(content)
+	}
+
+	fn strike(&mut self, _inline: &Inline, content: &[Inline]) -> String {
+		self.inlines(content)
+	}
+
+	fn under(&mut self, _inline: &Inline, content: &[Inline]) -> String {
+		self.inlines(content)
+	}
+}
+
+fn toc_entries(entries: &[TocEntry], depth: usize) -> String {
+	let mut out = String::new();
+	for entry in entries.iter() {
+		out += &("  ".repeat(depth) + "* " + &entry.title + "\n");
+		out += &toc_entries(&entry.children, depth + 1);
+	}
+	out
+}
+
+/// Breaks every line of some text between words, so that no line is longer than `width` characters unless it's a single word. Whitespace between words becomes a single space.
+fn wrap(text: &str, width: usize) -> String {
+	let mut lines: Vec<String> = Vec::new();
+	for line in text.split('\n') {
+		let mut current = String::new();
+		let mut length = 0;
+		for word in line.split_whitespace() {
+			let count = word.chars().count();
+			if length > 0 && length + 1 + count > width {
+				lines.push(std::mem::take(&mut current));
+				length = 0;
+			}
+			if length > 0 {
+				current.push(' ');
+				length += 1;
+			}
+			current += word;
+			length += count;
+		}
+		lines.push(current);
+	}
+	lines.join("\n")
+}
+
+/// Takes the tags out of some HTML, leaving the text between them.
+fn strip_tags(html: &str) -> String {
+	let mut out = String::new();
+	let mut rest = html;
+	while let Some(start) = rest.find('<') {
+		out += &rest[..start];
+		match rest[start..].find('>') {
+			Some(end) => rest = &rest[start + end + 1..],
+			None => {
+				rest = &rest[start..];
+				break
+			},
+		}
+	}
+	out + rest
+}
+
+/// Indents every line but the first by `width` spaces, leaving blank lines blank.
+fn indent(text: &str, width: usize) -> String {
+	let mut out = String::new();
+	for (iter, line) in text.split_inclusive('\n').enumerate() {
+		if iter > 0 && !line.trim().is_empty() {
+			out += &" ".repeat(width);
+		}
+		out += line;
+	}
+	out
+}
@@ -0,0 +1,67 @@
+use crate::ast::{plain_text, BlockKind, Document, TocEntry};
+use crate::html::footnote_ref_id;
+use crate::syntax::visit_document_blocks;
+
+/// Gives every header of the document that doesn't have an id one made from its text, like `getting-started` for `# Getting Started`. Ids that are already taken get a number after them. Headers inside other blocks, like quotes, list items or footnotes, get one too.
+pub fn header_ids(document: &mut Document) {
+	// The footnotes and their references are given ids when rendered, which headers can't take either
+	let mut taken: Vec<String> = Vec::new();
+	for note in document.footnotes.iter() {
+		taken.push(format!("fn-{}", note.number));
+		taken.extend((1..=note.references).map(|x| footnote_ref_id(note.number, x)));
+	}
+	visit_document_blocks(document, &mut |block| taken.extend(block.attributes.id.clone()));
+	visit_document_blocks(document, &mut |block| {
+		let BlockKind::Header { content, .. } = &block.kind else { return };
+		if block.attributes.id.is_some() {
+			return
+		}
+		let base = slug(&plain_text(content));
+		let mut id = base.clone();
+		let mut count = 0;
+		while taken.contains(&id) {
+			count += 1;
+			id = format!("{}-{}", base, count);
+		}
+		taken.push(id.clone());
+		block.attributes.id = Some(id);
+	});
+}
+
+/// Builds the table of contents of the document from its headers, including the ones inside other blocks, in the order they are rendered. Headers with a higher level than the one before them are nested under it.
+pub fn toc(document: &Document) -> Vec<TocEntry> {
+	let mut out: Vec<TocEntry> = Vec::new();
+	// The visitor needs blocks it could change, so it goes over a copy of them
+	visit_document_blocks(&mut document.clone(), &mut |block| {
+		if let BlockKind::Header { level, content } = &block.kind {
+			insert(&mut out, TocEntry { level: *level, id: block.attributes.id.clone(), title: plain_text(content), children: Vec::new() });
+		}
+	});
+	out
+}
+
+fn insert(entries: &mut Vec<TocEntry>, entry: TocEntry) {
+	match entries.last_mut() {
+		Some(last) if last.level < entry.level => insert(&mut last.children, entry),
+		_ => entries.push(entry),
+	}
+}
+
+/// Turns text into something that can be used as an id: lowercase, with dashes instead of spaces and without punctuation.
+pub fn slug(text: &str) -> String {
+	let mut out = String::new();
+	for cha in text.chars() {
+		if cha.is_alphanumeric() {
+			out.extend(cha.to_lowercase());
+		} else if (cha.is_whitespace() || cha == '-' || cha == '_') && !out.is_empty() && !out.ends_with('-') {
+			out.push('-');
+		}
+	}
+	while out.ends_with('-') {
+		out.pop();
+	}
+	if out.is_empty() {
+		out += "section";
+	}
+	out
+}
@@ -0,0 +1,31 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Renders `input` with `kami --deny-warnings`, which fails on any warning.
+fn render(input: &str) -> String {
+	let mut child = Command::new(env!("CARGO_BIN_EXE_kami")).arg("--deny-warnings").stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn().unwrap();
+	child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+	let output = child.wait_with_output().unwrap();
+	assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+	String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn email() {
+	assert_eq!(render("Write to me@example.com.\n"), "<p >Write to <a href=\"mailto:me@example.com\" >me@example.com</a>.</p>\n");
+}
+
+#[test]
+fn two_emails_on_a_line() {
+	assert_eq!(render("Write to a@example.com or b@example.org\n"), "<p >Write to <a href=\"mailto:a@example.com\" >a@example.com</a> or <a href=\"mailto:b@example.org\" >b@example.org</a></p>\n");
+}
+
+#[test]
+fn url_with_formatting_characters() {
+	assert_eq!(render("See https://example.com/a_b~c_d~ now\n"), "<p >See <a href=\"https://example.com/a_b~c_d~\" >https://example.com/a_b~c_d~</a> now</p>\n");
+}
+
+#[test]
+fn span_after_word() {
+	assert_eq!(render("word@span@ x and 2@3@4\n"), "<p >word<span >span</span> x and 2<span >3</span>4</p>\n");
+}
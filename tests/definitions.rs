@@ -0,0 +1,12 @@
+use kami_parser::syntax;
+
+#[test]
+fn definition_list() {
+	let html = syntax::parse("{#glossary}\n; Token\n; Lexeme\n: A piece of the text\n  with more.\n; Block\n: {.important} A paragraph.\n").0;
+	assert_eq!(html, "<dl id=\"glossary\">\n<dt >Token</dt>\n<dt >Lexeme</dt>\n<dd >A piece of the text</br>with more.</dd>\n<dt >Block</dt>\n<dd class=\"important\">A paragraph.</dd>\n</dl>\n");
+}
+
+#[test]
+fn blocks_in_definition() {
+	assert_eq!(syntax::parse("; Term\n: First\n\n  * item\n: Second\n").0, "<dl >\n<dt >Term</dt>\n<dd >First\n<ul >\n<li >item</li>\n</ul>\n</dd>\n<dd >Second</dd>\n</dl>\n");
+}
@@ -0,0 +1,26 @@
+use kami_parser::syntax;
+
+/// The code, line and column of every warning of a document.
+fn warnings(input: &str) -> Vec<(&'static str, usize, usize)> {
+	syntax::parse(input).1.iter().map(|x| (x.code, x.line, x.column)).collect()
+}
+
+#[test]
+fn unclosed_token_in_table_cell() {
+	assert_eq!(warnings("| a | b *c |\n"), vec![("unclosed-token", 1, 9)]);
+}
+
+#[test]
+fn unclosed_token_inside_closed_token() {
+	assert_eq!(warnings("*a _b* and [c ~d](e)\n"), vec![("unclosed-token", 1, 4), ("unclosed-token", 1, 15)]);
+}
+
+#[test]
+fn verbatim_content_has_no_unclosed_tokens() {
+	assert_eq!(warnings("`a*b` <a href=\"x_y\"> [^a_b]\n\n[^a_b]: Note\n"), vec![]);
+}
+
+#[test]
+fn unclosed_token_in_quote_attribution() {
+	assert_eq!(warnings("> Quoted\n> -- *Someone\n"), vec![("unclosed-token", 2, 6)]);
+}
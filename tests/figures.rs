@@ -0,0 +1,20 @@
+use kami_parser::syntax;
+
+fn html(input: &str) -> String {
+	syntax::parse(input).0
+}
+
+#[test]
+fn image_figure() {
+	assert_eq!(html("!cat.png!{alt=\"A cat\"}\n!! A *cat*\n"), "<figure id=\"fig-1\">\n<img alt=\"A cat\" loading=\"lazy\" src=\"cat.png\"/>\n<figcaption>Figure 1: A <b >cat</b></figcaption>\n</figure>\n");
+}
+
+#[test]
+fn table_and_code_figures() {
+	assert_eq!(html("| a |\n!!{#t} Table\n\n```\ncode\n```\n!! Code\n"), "<figure id=\"t\">\n<table >\n<tr >\n<td >a </td>\n</tr>\n</table>\n<figcaption>Figure 1: Table</figcaption>\n</figure>\n<figure id=\"fig-2\">\n<pre ><code>code\n</code></pre>\n<figcaption>Figure 2: Code</figcaption>\n</figure>\n");
+}
+
+#[test]
+fn links_to_figures() {
+	assert_eq!(html("See [](#fig-a) and [](#fig-2).\n\n!a.png!\n!!{#fig-a} First\n\n!b.png!\n!! Second\n"), "<p >See <a href=\"#fig-a\" >Figure 1</a> and <a href=\"#fig-2\" >Figure 2</a>.</p>\n<figure id=\"fig-a\">\n<img loading=\"lazy\" src=\"a.png\"/>\n<figcaption>Figure 1: First</figcaption>\n</figure>\n<figure id=\"fig-2\">\n<img loading=\"lazy\" src=\"b.png\"/>\n<figcaption>Figure 2: Second</figcaption>\n</figure>\n");
+}
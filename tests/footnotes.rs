@@ -0,0 +1,19 @@
+use kami_parser::ast::BlockKind;
+use kami_parser::syntax;
+
+#[test]
+fn definition_ends_list_and_table() {
+	let (document, _) = syntax::parse_document("* a\n[^x]: Note\n* b\n\n| c |\n[^y]: Other\n| d |\n\nSee[^x] and[^y]\n");
+	let lists = document.blocks.iter().filter(|x| matches!(&x.kind, BlockKind::List { items, .. } if items.len() == 1)).count();
+	let tables = document.blocks.iter().filter(|x| matches!(&x.kind, BlockKind::Table { body, .. } if body.len() == 1)).count();
+	assert_eq!((lists, tables, document.blocks.len()), (2, 2, 5));
+	assert_eq!(document.footnotes.len(), 2);
+}
+
+#[test]
+fn link_definition_ends_list_and_table() {
+	let (document, _) = syntax::parse_document("* a\n[x]: https://example.com\n* b\n\n| c |\n[y]: https://example.org\n| d |\n\nSee [x] and [y]\n");
+	let lists = document.blocks.iter().filter(|x| matches!(&x.kind, BlockKind::List { items, .. } if items.len() == 1)).count();
+	let tables = document.blocks.iter().filter(|x| matches!(&x.kind, BlockKind::Table { body, .. } if body.len() == 1)).count();
+	assert_eq!((lists, tables), (2, 2));
+}
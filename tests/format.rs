@@ -0,0 +1,62 @@
+use kami_parser::format;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+#[test]
+fn attribute_order() {
+	assert_eq!(format::format("#{k=\"v\" .c #i} Title\n\n{data-x=1 .a .b #p}Para [l](u){k=v #q}\n").unwrap(), "#{#i .c k=\"v\"} Title\n\n{#p .a .b data-x=\"1\"}Para [l](u){#q k=\"v\"}\n");
+}
+
+#[test]
+fn list_markers() {
+	assert_eq!(format::format("* a\n*** b\n#. c\n##. d\n").unwrap(), "* a\n** b\n#. c\n##. d\n");
+}
+
+#[test]
+fn table_alignment() {
+	assert_eq!(format::format("|r2c1*{.x} a |bb |\n| long cell |\n|c2 wide |\n").unwrap(), "|r2c1*{.x} a \t| bb \t|\n| long cell \t\t\t|\n|c2 wide \t\t\t\t|\n");
+}
+
+#[test]
+fn blank_lines() {
+	assert_eq!(format::format("a\n\n\n\nb\n\n\n\n\n* c\n\n\n").unwrap(), "a\n\nb\n\n* c\n");
+}
+
+/// An empty directory for a test to write documents into.
+fn temp_dir(name: &str) -> PathBuf {
+	let dir = std::env::temp_dir().join(format!("kamifmt-{}-{}", name, std::process::id()));
+	let _ = fs::remove_dir_all(&dir);
+	fs::create_dir_all(dir.join("nested")).unwrap();
+	dir
+}
+
+fn kamifmt(args: &[&str]) -> (i32, String) {
+	let output = Command::new(env!("CARGO_BIN_EXE_kamifmt")).args(args).output().unwrap();
+	(output.status.code().unwrap(), String::from_utf8(output.stdout).unwrap())
+}
+
+#[test]
+fn check() {
+	let dir = temp_dir("check");
+	let messy = dir.join("messy.km");
+	fs::write(&messy, "a\n\n\n\nb\n").unwrap();
+	fs::write(dir.join("tidy.km"), "a\n\nb\n").unwrap();
+	assert_eq!(kamifmt(&["--check", dir.to_str().unwrap()]), (1, format!("{}\n", messy.display())));
+	assert_eq!(fs::read_to_string(&messy).unwrap(), "a\n\n\n\nb\n");
+	assert_eq!(kamifmt(&["--check", dir.join("tidy.km").to_str().unwrap()]), (0, String::new()));
+	fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn directories() {
+	let dir = temp_dir("directories");
+	fs::write(dir.join("a.km"), "* a\n*** b\n").unwrap();
+	fs::write(dir.join("nested").join("b.km"), "a\n\n\n\nb\n").unwrap();
+	fs::write(dir.join("nested").join("c.md"), "a\n\n\n\nb\n").unwrap();
+	assert_eq!(kamifmt(&[dir.to_str().unwrap()]), (0, String::new()));
+	assert_eq!(fs::read_to_string(dir.join("a.km")).unwrap(), "* a\n** b\n");
+	assert_eq!(fs::read_to_string(dir.join("nested").join("b.km")).unwrap(), "a\n\nb\n");
+	assert_eq!(fs::read_to_string(dir.join("nested").join("c.md")).unwrap(), "a\n\n\n\nb\n");
+	fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,22 @@
+use kami_parser::syntax::{self, Options};
+
+fn html(input: &str) -> String {
+	syntax::parse(input).0
+}
+
+#[test]
+fn dimensions() {
+	assert_eq!(html("!img.png =640x480!\n\n!img.png =640x!\n"), "<img width=\"640\" height=\"480\" loading=\"lazy\" src=\"img.png\"/>\n<img width=\"640\" loading=\"lazy\" src=\"img.png\"/>\n");
+}
+
+#[test]
+fn srcset() {
+	assert_eq!(html("!img.png, img-2x.png 2x, img-big.png 1600w =640x480!{sizes=\"50vw\"}\n"), "<img sizes=\"50vw\" width=\"640\" height=\"480\" loading=\"lazy\" src=\"img.png\" srcset=\"img-2x.png 2x, img-big.png 1600w\"/>\n");
+}
+
+#[test]
+fn lazy_loading() {
+	assert_eq!(html("!a.png!{loading=eager}\n"), "<img loading=\"eager\" src=\"a.png\"/>\n");
+	let options = Options { lazy_images: false, ..Options::default() };
+	assert_eq!(syntax::parse_with("Text !a.png! [!b.png!](x)\n", &options).0, "<p >Text <img  src=\"a.png\"/> <a href=\"x\" ><img  src=\"b.png\"/></a></p>\n");
+}
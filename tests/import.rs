@@ -0,0 +1,34 @@
+use kami_parser::import;
+
+/// The Kami that a Markdown document becomes, checking that nothing was lost on the way.
+fn kami(input: &str) -> String {
+	let (out, warnings) = import::markdown(input);
+	assert!(warnings.is_empty(), "{:?}", warnings);
+	out
+}
+
+#[test]
+fn gfm_table() {
+	assert_eq!(kami("| A | B | C |\n|:--|:-:|--:|\n| 1 | `x\\|y` | a \\| b |\n"), "| A | B | C |\n|:---|:---:|---:|\n| 1 | <code>x\\\\=&#124;=y</code> | a \\\\=&#124;= b |\n");
+}
+
+#[test]
+fn nested_lists() {
+	assert_eq!(kami("- a\n  - b\n    1. c\n- d\n"), "* a\n** b\n###. c\n* d\n");
+}
+
+#[test]
+fn fenced_code() {
+	assert_eq!(kami("```rust\nlet a = *b;\n```\n"), "```rust\nlet a = *b;\n```\n");
+}
+
+#[test]
+fn reference_links() {
+	assert_eq!(kami("A [link][ref] and [ref].\n\n[ref]: https://example.com \"Title\"\n"), "A [link][ref] and [ref][ref].\n\n[ref]: https://example.com \"Title\"\n");
+}
+
+#[test]
+fn escaping() {
+	assert_eq!(kami("\\* a\\@b @c \\` \\\\ \\| d|e `f`\n"), "\\* a\\@b \\@c \\` \\\\ | d|e `f`\n");
+	assert_eq!(kami("\\| starts\n"), "\\| starts\n");
+}
@@ -0,0 +1,20 @@
+use kami_parser::syntax;
+
+fn html(input: &str) -> String {
+	syntax::parse(input).0
+}
+
+#[test]
+fn nested_lists() {
+	assert_eq!(html("* a\n** b\n###. c\n* d\n"), "<ul >\n<li >a\n<ul >\n<li >b\n<ol >\n<li >c</li>\n</ol>\n</li>\n</ul>\n</li>\n<li >d</li>\n</ul>\n");
+}
+
+#[test]
+fn continued_item() {
+	assert_eq!(html("* A long element\n  that keeps going\n* Next\n"), "<ul >\n<li >A long element</br>that keeps going</li>\n<li >Next</li>\n</ul>\n");
+}
+
+#[test]
+fn blocks_in_item() {
+	assert_eq!(html("#. First\n\n   A second paragraph.\n\n   ```\n   some code\n   ```\n#. Next\n"), "<ol >\n<li >First\n<p >A second paragraph.</p>\n<pre ><code>some code\n</code></pre>\n</li>\n<li >Next</li>\n</ol>\n");
+}
@@ -0,0 +1,16 @@
+use kami_parser::{markdown, syntax};
+
+fn export(input: &str) -> String {
+	markdown::render(&syntax::parse_document(input).0).0
+}
+
+#[test]
+fn line_break() {
+	assert_eq!(export("First line\\n  second *line*\n"), "First line\\\nsecond **line**\n");
+	assert_eq!(export("* Item\\n more\n"), "- Item\\\n  more\n");
+}
+
+#[test]
+fn exclamation_mark_before_link() {
+	assert_eq!(export("Wow\\![a](b) and a\\\\\\![c](d)\n"), "Wow\\![a](b) and a\\\\\\![c](d)\n");
+}
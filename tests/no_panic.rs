@@ -0,0 +1,95 @@
+use kami_parser::{format, import, markdown, syntax, text};
+use std::panic::RefUnwindSafe;
+
+// Characters that mean something to Kami, weighted so that random documents are mostly syntax
+const ALPHABET: &[char] = &[
+	'*', '_', '~', '^', '@', '`', '-', '!', '[', ']', '(', ')', '{', '}', '<', '>', '|', '#', '.', '=', '\\',
+	'"', ':', ';', 'c', 'r', '1', '2', 'n', 'a', ' ', ' ', '\t', '\n', '\n', '\r', 'é', 'ü', '日', '🦀',
+];
+
+/// A small xorshift generator, so the test doesn't need any dependencies and is reproducible.
+struct Rng(u64);
+
+impl Rng {
+	fn next(&mut self) -> u64 {
+		self.0 ^= self.0 << 13;
+		self.0 ^= self.0 >> 7;
+		self.0 ^= self.0 << 17;
+		self.0
+	}
+
+	fn below(&mut self, max: usize) -> usize {
+		(self.next() % max as u64) as usize
+	}
+
+	fn document(&mut self, len: usize) -> String {
+		let mut out = String::new();
+		for _ in 0..len {
+			if self.below(8) == 0 {
+				// Any unicode scalar value at all
+				if let Some(cha) = char::from_u32(self.below(0x110000) as u32) {
+					out.push(cha);
+				}
+			} else {
+				out.push(ALPHABET[self.below(ALPHABET.len())]);
+			}
+		}
+		out
+	}
+}
+
+/// Calls `f` with `count` random documents, failing on the first one it panics on. The documents are the same for the same seed, and are returned with what `f` made of them.
+fn never_panics<T>(name: &str, seed: u64, count: usize, f: impl Fn(&str) -> T + RefUnwindSafe) -> Vec<(String, T)> {
+	let mut rng = Rng(seed);
+	let mut out = Vec::new();
+	for _ in 0..count {
+		let len = rng.below(80);
+		let document = rng.document(len);
+		match std::panic::catch_unwind(|| f(&document)) {
+			Ok(result) => out.push((document, result)),
+			Err(_) => panic!("{} panicked on {:?}", name, document),
+		}
+	}
+	out
+}
+
+#[test]
+fn parse_never_panics() {
+	never_panics("parse", 0x2545F4914F6CDD1D, 20000, syntax::parse);
+}
+
+#[test]
+fn markdown_never_panics() {
+	never_panics("Markdown rendering", 0x9E3779B97F4A7C15, 5000, |x| markdown::render(&syntax::parse_document(x).0));
+}
+
+#[test]
+fn text_never_panics() {
+	never_panics("Text rendering", 0x94D049BB133111EB, 5000, |x| {
+		let (document, _) = syntax::parse_document(x);
+		let width = x.len() % 40;
+		text::render_with(&document, &syntax::Options::default(), Some(width));
+		text::summary(&document, width)
+	});
+}
+
+#[test]
+fn import_never_panics() {
+	never_panics("import", 0xD1B54A32D192ED03, 20000, import::markdown);
+}
+
+#[test]
+fn format_never_panics() {
+	for (document, result) in never_panics("format", 0xBF58476D1CE4E5B9, 5000, format::format) {
+		if let Ok(out) = result {
+			assert_eq!(syntax::parse(&document).0, syntax::parse(&out).0, "format changed the HTML of {:?}", document);
+		}
+	}
+}
+
+#[test]
+fn parse_handles_long_unclosed_lines() {
+	for delimiter in ["[", "*[", "(", "{", "<", "!a", "`", "@", ">"] {
+		syntax::parse(&delimiter.repeat(2000));
+	}
+}
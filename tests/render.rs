@@ -0,0 +1,30 @@
+use kami_parser::ast::Inline;
+use kami_parser::html::{self, HtmlRenderer};
+use kami_parser::render::Renderer;
+use kami_parser::syntax::{self, Options};
+
+struct NewTabLinks(Options);
+
+impl Renderer for NewTabLinks {
+	fn options(&self) -> &Options {
+		&self.0
+	}
+
+	fn link(&mut self, _inline: &Inline, destination: Option<&str>, _title: Option<&str>, content: &[Inline]) -> String {
+		format!("<a href=\"{}\" target=\"_blank\">{}</a>", html::encode_attr(destination.unwrap_or_default()), self.inlines(content))
+	}
+}
+
+#[test]
+fn html_renderer_matches_parse() {
+	let input = "# Title\n\n*Bold* [link](x)\n\n* a\n** b\n\n| c |r2 d |\n| e |\n\nNote[^a]\n\n[^a]: Footnote\n";
+	let (document, _) = syntax::parse_document(input);
+	let options = Options::default();
+	assert_eq!(HtmlRenderer::new(&options).document(&document), syntax::parse(input).0);
+}
+
+#[test]
+fn overridden_method_renders_nested_nodes() {
+	let (document, _) = syntax::parse_document("*[a](x)*\n\n| [b](y) |\n");
+	assert_eq!(NewTabLinks(Options::default()).document(&document), "<p ><b ><a href=\"x\" target=\"_blank\">a</a></b></p>\n<table >\n<tr >\n<td ><a href=\"y\" target=\"_blank\">b</a> </td>\n</tr>\n</table>\n");
+}
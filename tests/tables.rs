@@ -0,0 +1,49 @@
+use kami_parser::syntax::{self, Options};
+
+fn html(input: &str) -> String {
+	syntax::parse(input).0
+}
+
+/// The code, line and column of every warning of a document.
+fn warnings(input: &str) -> Vec<(&'static str, usize, usize)> {
+	syntax::parse(input).1.iter().map(|x| (x.code, x.line, x.column)).collect()
+}
+
+#[test]
+fn sections_alignment_and_caption() {
+	let input = "|+ Fruit prices\n| Fruit | Price |\n|:------|------:{.price}|\n| Apple | 1 |\n|===|\n| Total | 3 |\n";
+	assert_eq!(html(input), "<table >\n<caption>Fruit prices</caption>\n<colgroup>\n<col >\n<col class=\"price\">\n</colgroup>\n<thead>\n<tr >\n<th style=\"text-align: left\">Fruit </th>\n<th style=\"text-align: right\">Price </th>\n</tr>\n</thead>\n<tbody>\n<tr >\n<td style=\"text-align: left\">Apple </td>\n<td style=\"text-align: right\">1 </td>\n</tr>\n</tbody>\n<tfoot>\n<tr >\n<td style=\"text-align: left\">Total </td>\n<td style=\"text-align: right\">3 </td>\n</tr>\n</tfoot>\n</table>\n");
+}
+
+#[test]
+fn header_row_is_head() {
+	assert_eq!(html("|* Name |* Age |\n| A | 1 |\n"), "<table >\n<thead>\n<tr >\n<th >Name </th>\n<th >Age </th>\n</tr>\n</thead>\n<tbody>\n<tr >\n<td >A </td>\n<td >1 </td>\n</tr>\n</tbody>\n</table>\n");
+}
+
+#[test]
+fn rows_of_the_wrong_width() {
+	assert_eq!(warnings("| a | b |\n| c |\n| d | e | f |\n"), vec![("table-row-too-short", 2, 1), ("table-row-too-long", 3, 1)]);
+}
+
+#[test]
+fn spans_counted_in_width() {
+	assert_eq!(warnings("|c2 a |\n|r2 b | c |\n| d |\n"), vec![]);
+	assert_eq!(warnings("| a |r2 b |\n|c2 c | d |\n"), vec![("table-cell-overlap", 2, 1), ("table-row-too-long", 2, 1)]);
+	assert_eq!(warnings("|r3 a | b |\n| c |\n"), vec![("table-rowspan-past-end", 1, 1)]);
+}
+
+#[test]
+fn padded_rows() {
+	let options = Options { pad_tables: true, ..Options::default() };
+	assert_eq!(syntax::parse_with("| a | b |\n| c |\n", &options).0, "<table >\n<tr >\n<td >a </td>\n<td >b </td>\n</tr>\n<tr >\n<td >c </td>\n<td ></td>\n</tr>\n</table>\n");
+}
+
+#[test]
+fn block_cells() {
+	assert_eq!(html("| Apple | ::: |\n:::\n* Red\n\nCrunchy.\n:::\n| Pear | Soft |\n"), "<table >\n<tr >\n<td >Apple </td>\n<td >\n<ul >\n<li >Red</li>\n</ul>\n<p >Crunchy.</p>\n</td>\n</tr>\n<tr >\n<td >Pear </td>\n<td >Soft </td>\n</tr>\n</table>\n");
+}
+
+#[test]
+fn nested_block_cells() {
+	assert_eq!(html("| a | ::: |\n:::\n| b | ::: |\n::::\nInner\n::::\n:::\n"), "<table >\n<tr >\n<td >a </td>\n<td >\n<table >\n<tr >\n<td >b </td>\n<td >\n<p >Inner</p>\n</td>\n</tr>\n</table>\n</td>\n</tr>\n</table>\n");
+}
@@ -0,0 +1,47 @@
+use kami_parser::syntax::{self, Options};
+use kami_parser::text;
+
+fn summary(input: &str, length: usize) -> String {
+	text::summary(&syntax::parse_document(input).0, length)
+}
+
+fn wrapped(input: &str, width: usize) -> String {
+	text::render_with(&syntax::parse_document(input).0, &Options::default(), Some(width))
+}
+
+#[test]
+fn summary_of_exact_length() {
+	assert_eq!(summary("Hello *world*\n", 11), "Hello world");
+}
+
+#[test]
+fn summary_cut_after_word() {
+	assert_eq!(summary("Hello world\n", 10), "Hello…");
+}
+
+#[test]
+fn summary_cut_mid_word() {
+	assert_eq!(summary("Unbelievable\n", 5), "Unbe…");
+}
+
+#[test]
+fn summary_of_zero_length() {
+	assert_eq!(summary("Hello world\n", 0), "");
+}
+
+#[test]
+fn summary_of_multibyte_text() {
+	assert_eq!(summary("Héllo wörld\n", 5), "Héll…");
+	assert_eq!(summary("Héllo wörld\n", 10), "Héllo…");
+}
+
+#[test]
+fn line_break() {
+	assert_eq!(text::render(&syntax::parse_document("One\\n  two *b*\n").0), "One\ntwo b\n");
+}
+
+#[test]
+fn wrapping_in_list_items() {
+	assert_eq!(wrapped("* item with some words that wrap around\\n  more words\n** nested item that is long enough to wrap\n", 20), "* item with some\n  words that wrap\n  around\n  more words\n  * nested item that\n    is long enough\n    to wrap\n");
+	assert_eq!(wrapped("#. numbered item that is long too\n", 20), "1. numbered item\n   that is long too\n");
+}
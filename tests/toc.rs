@@ -0,0 +1,18 @@
+use kami_parser::ast::{BlockKind, TocEntry};
+use kami_parser::syntax;
+
+#[test]
+fn header_in_quote() {
+	let (document, _) = syntax::parse_document("[[toc]]\n\n> # Inside\n");
+	let BlockKind::Quote { content, .. } = &document.blocks[1].kind else { panic!("not a quote") };
+	assert_eq!(content[0].attributes.id.as_deref(), Some("inside"));
+	let BlockKind::Toc { entries } = &document.blocks[0].kind else { panic!("not a table of contents") };
+	assert_eq!(entries, &vec![TocEntry { level: 1, id: Some("inside".to_owned()), title: "Inside".to_owned(), children: Vec::new() }]);
+}
+
+#[test]
+fn header_ids_skip_footnote_ids() {
+	let (document, _) = syntax::parse_document("[[toc]]\n\n# Fn 1\n\n# Fnref 1\n\nNote[^a]\n\n[^a]: Note\n");
+	let ids: Vec<Option<&str>> = document.blocks[1..3].iter().map(|x| x.attributes.id.as_deref()).collect();
+	assert_eq!(ids, vec![Some("fn-1-1"), Some("fnref-1-1")]);
+}